@@ -0,0 +1,85 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use nations::procgen::noise::simplex_noise::{
+    FixedSimplexNoise, SimplexNoise, TiledOctavedSimplexNoise,
+};
+use nations::procgen::noise::{to_axes, Noise};
+use nations::procgen::procseed::ProcSeed;
+use std::hint::black_box;
+
+fn points(dim: usize) -> Vec<Vec<f64>> {
+    (0..256)
+        .map(|k| {
+            (0..dim)
+                .map(|i| (k as f64 * 0.737 + i as f64 * 0.291).fract() * 16.0)
+                .collect()
+        })
+        .collect()
+}
+
+fn bench_dim<const N: usize>(c: &mut Criterion) {
+    let seed = ProcSeed::new(&0u32, 0.0);
+    let generic = SimplexNoise::new(N);
+    let fixed = FixedSimplexNoise::<N>::new();
+    let vecs = points(N);
+    let arrays: Vec<[f64; N]> = vecs
+        .iter()
+        .map(|p| {
+            let mut a = [0.0; N];
+            a.copy_from_slice(p);
+            a
+        })
+        .collect();
+
+    let mut group = c.benchmark_group(format!("simplex {}d", N));
+    group.bench_function("generic", |b| {
+        b.iter(|| {
+            for p in vecs.iter() {
+                black_box(generic.get_generic(&seed, black_box(p)));
+            }
+        })
+    });
+    group.bench_function("fixed", |b| {
+        b.iter(|| {
+            for p in arrays.iter() {
+                black_box(fixed.get(&seed, black_box(p)));
+            }
+        })
+    });
+    let axes = to_axes(&vecs);
+    group.bench_function("batch", |b| {
+        b.iter(|| black_box(fixed.get_noise_batch(&seed, black_box(&axes))))
+    });
+    group.finish();
+}
+
+/// The layer the terrain samples four times per hex, which runs five dimensional simplex noise
+/// once per octave.
+fn bench_terrain_layer(c: &mut Criterion) {
+    let seed = ProcSeed::new(&0u32, 0.0);
+    let layer = TiledOctavedSimplexNoise::skewed_octaved(2, 6, 0.5, 1.0, 0.5);
+    let vecs = points(2);
+    let axes = to_axes(&vecs);
+    let mut group = c.benchmark_group("terrain layer");
+    group.bench_function("points", |b| {
+        b.iter(|| {
+            for p in vecs.iter() {
+                black_box(layer.get_noise(&seed, black_box(p)));
+            }
+        })
+    });
+    group.bench_function("batch", |b| {
+        b.iter(|| black_box(layer.get_noise_batch(&seed, black_box(&axes))))
+    });
+    group.finish();
+}
+
+fn simplex(c: &mut Criterion) {
+    bench_dim::<2>(c);
+    bench_dim::<3>(c);
+    bench_dim::<4>(c);
+    bench_dim::<5>(c);
+    bench_terrain_layer(c);
+}
+
+criterion_group!(benches, simplex);
+criterion_main!(benches);
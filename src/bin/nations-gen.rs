@@ -0,0 +1,336 @@
+use nations::game::world::erosion::ErosionConfig;
+use nations::game::world::TiledGameWorld;
+use nations::geometry::Orientation;
+use nations::procgen::procseed::ProcSeed;
+use nations::procgen::terrain::biome::BiomeTable;
+use nations::procgen::terrain::config::TerrainConfig;
+use nations::procgen::terrain::{MapBiome, TerrainSample, TiledWorldTerrain};
+use nations::render::{render_world, Channel, RenderOptions, Sampling};
+use serde::Serialize;
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "\
+Generates a world without opening a window.
+
+USAGE:
+    nations-gen [OPTIONS]
+
+OPTIONS:
+    --seed <SEED>        seed as printed by the game (e.g. 2-CR8J7PYDC92QG-0); text that does
+                         not start with a version number is hashed into a new seed
+                         [default: the game's default seed]
+    --seed-text <TEXT>   hash any text, even one that looks like a seed, into a new seed
+    --skew <SKEW>        overrides the seed's skew
+    --width <CELLS>      world width [default: 200]
+    --height <CELLS>     world height [default: 150]
+    --preset <NAME>      terrain preset: default, archipelago, pangaea, ice-age or highlands
+                         [default: default]
+    --config <PATH>      load the terrain configuration from a RON file instead of a preset
+    --dump-config        print the selected terrain configuration as RON and exit
+    --biomes <PATH>      load the biome classification table from a RON file; its water level
+                         replaces the configuration's sea level
+    --dump-biomes        print the built-in biome table as RON and exit
+    --erode <ROUNDS>     carve the heightmap with this many rounds of hydraulic and thermal
+                         erosion
+    --erosion-config <PATH>
+                         erode with the parameters in a RON file; --erode overrides its number
+                         of rounds
+    --png <PATH>         write a rendered map
+    --channel <NAME>     what the map shows: biome, shaded, height, temperature, precipitation
+                         or texture [default: shaded]
+    --hex <RADIUS>       draw the map as hexes of this radius in pixels instead of per pixel
+    --flat               draw flat-topped hexes instead of pointy-topped ones
+    --scale <PIXELS>     pixels per world cell in the map [default: 1]
+    --csv <PATH>         write one row per cell: x, y, height, temperature, precipitation, biome
+    --json <PATH>        write each layer as a row-major grid
+    --stats              print summary statistics (the default when no file is written)
+    --help               print this message";
+
+struct Args {
+    seed: ProcSeed,
+    width: usize,
+    height: usize,
+    config: TerrainConfig,
+    biomes: Option<String>,
+    erosion: Option<ErosionConfig>,
+    png: Option<String>,
+    channel: Channel,
+    sampling: Sampling,
+    orientation: Orientation,
+    scale: u32,
+    csv: Option<String>,
+    json: Option<String>,
+    stats: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        seed: ProcSeed::new(&0u32, 0.0),
+        width: 200,
+        height: 150,
+        config: TerrainConfig::default(),
+        biomes: None,
+        erosion: None,
+        png: None,
+        channel: Channel::Shaded,
+        sampling: Sampling::Square,
+        orientation: Orientation::POINTY,
+        scale: 1,
+        csv: None,
+        json: None,
+        stats: false,
+    };
+    let mut skew = None;
+    let mut rounds = None;
+
+    let mut input = std::env::args().skip(1);
+    while let Some(flag) = input.next() {
+        if flag == "--help" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        if flag == "--dump-biomes" {
+            println!("{}", BiomeTable::default().to_ron().unwrap());
+            process::exit(0);
+        }
+        if flag == "--dump-config" {
+            println!("{}", args.config.to_ron().unwrap());
+            process::exit(0);
+        }
+        if flag == "--stats" {
+            args.stats = true;
+            continue;
+        }
+        if flag == "--flat" {
+            args.orientation = Orientation::FLAT;
+            continue;
+        }
+        let value = input
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        let invalid = format!("invalid value for {}: {:?}", flag, value);
+        match flag.as_str() {
+            "--seed" => {
+                args.seed = match value.parse() {
+                    Ok(seed) => seed,
+                    Err(e) if looks_like_seed(&value) => {
+                        return Err(format!("invalid seed {:?}: {}", value, e))
+                    }
+                    Err(_) => ProcSeed::new(&value, args.seed.get_skew()),
+                }
+            }
+            "--seed-text" => args.seed = ProcSeed::new(&value, args.seed.get_skew()),
+            "--skew" => skew = Some(value.parse().map_err(|_| invalid.clone())?),
+            "--width" => args.width = value.parse().map_err(|_| invalid.clone())?,
+            "--height" => args.height = value.parse().map_err(|_| invalid.clone())?,
+            "--preset" => args.config = TerrainConfig::preset(&value).ok_or(invalid)?,
+            "--config" => args.config = TerrainConfig::load(&value).map_err(|e| e.to_string())?,
+            "--biomes" => args.biomes = Some(value),
+            "--erode" => rounds = Some(value.parse().map_err(|_| invalid.clone())?),
+            "--erosion-config" => {
+                args.erosion = Some(ErosionConfig::load(&value).map_err(|e| e.to_string())?)
+            }
+            "--png" => args.png = Some(value),
+            "--channel" => {
+                args.channel = match value.as_str() {
+                    "biome" => Channel::Biome,
+                    "shaded" => Channel::Shaded,
+                    "height" => Channel::Height,
+                    "temperature" => Channel::Temperature,
+                    "precipitation" => Channel::Precipitation,
+                    "texture" => Channel::Texture,
+                    _ => return Err(invalid),
+                }
+            }
+            "--hex" => args.sampling = Sampling::Hex(value.parse().map_err(|_| invalid.clone())?),
+            "--scale" => args.scale = value.parse().map_err(|_| invalid.clone())?,
+            "--csv" => args.csv = Some(value),
+            "--json" => args.json = Some(value),
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+
+    if let Some(skew) = skew {
+        args.seed.skew = skew;
+    }
+    if let Some(rounds) = rounds {
+        args.erosion = Some(ErosionConfig {
+            iterations: rounds,
+            ..args.erosion.unwrap_or_default()
+        });
+    }
+    if args.width == 0 || args.height == 0 || args.scale == 0 {
+        return Err("world size and scale must be positive".to_string());
+    }
+    if args.png.is_none() && args.csv.is_none() && args.json.is_none() {
+        args.stats = true;
+    }
+    Ok(args)
+}
+
+/// Whether `text` starts like a printed seed (`<version>-...`), so failing to parse it is a typo
+/// to report rather than free text to hash.
+fn looks_like_seed(text: &str) -> bool {
+    match text.find('-') {
+        Some(i) => i > 0 && text[..i].bytes().all(|b| b.is_ascii_digit()),
+        None => false,
+    }
+}
+
+fn write_csv(path: &str, width: usize, cells: &[TerrainSample]) -> Result<(), Box<dyn Error>> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "x,y,height,temperature,precipitation,biome")?;
+    for (i, cell) in cells.iter().enumerate() {
+        writeln!(
+            w,
+            "{},{},{},{},{},{:?}",
+            i % width,
+            i / width,
+            cell.height,
+            cell.temperature,
+            cell.precipitation,
+            cell.biome
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct JsonWorld {
+    seed: String,
+    width: usize,
+    height: usize,
+    layers: JsonLayers,
+}
+
+/// Each layer as a row-major grid. Non-finite values are written as `null`.
+#[derive(Serialize)]
+struct JsonLayers {
+    height: Vec<Vec<f64>>,
+    temperature: Vec<Vec<f64>>,
+    precipitation: Vec<Vec<f64>>,
+    biome: Vec<Vec<String>>,
+}
+
+fn write_json(
+    path: &str,
+    seed: &ProcSeed,
+    width: usize,
+    height: usize,
+    cells: &[TerrainSample],
+) -> Result<(), Box<dyn Error>> {
+    let layer = |f: &dyn Fn(&TerrainSample) -> f64| {
+        cells
+            .chunks(width)
+            .map(|row| row.iter().map(f).collect())
+            .collect()
+    };
+    let world = JsonWorld {
+        seed: seed.to_string(),
+        width: width,
+        height: height,
+        layers: JsonLayers {
+            height: layer(&|c| c.height),
+            temperature: layer(&|c| c.temperature),
+            precipitation: layer(&|c| c.precipitation),
+            biome: cells
+                .chunks(width)
+                .map(|row| row.iter().map(|c| format!("{:?}", c.biome)).collect())
+                .collect(),
+        },
+    };
+    let mut w = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut w, &world)?;
+    writeln!(w)?;
+    Ok(())
+}
+
+fn print_stats(
+    seed: &ProcSeed,
+    width: usize,
+    height: usize,
+    water_level: f64,
+    cells: &[TerrainSample],
+) {
+    let total = cells.len() as f64;
+    let mean = |f: &dyn Fn(&TerrainSample) -> f64| cells.iter().map(f).sum::<f64>() / total;
+    let water = cells.iter().filter(|c| c.height < water_level).count() as f64;
+
+    println!("seed:               {}", seed);
+    println!("size:               {} x {}", width, height);
+    println!(
+        "land / water:       {:.1}% / {:.1}%",
+        100.0 * (total - water) / total,
+        100.0 * water / total
+    );
+    println!("mean height:        {:.3}", mean(&|c| c.height));
+    println!("mean temperature:   {:.3}", mean(&|c| c.temperature));
+    println!("mean precipitation: {:.3}", mean(&|c| c.precipitation));
+
+    let mut counts: Vec<(MapBiome, usize)> = MapBiome::ALL
+        .iter()
+        .map(|b| (*b, cells.iter().filter(|c| c.biome == *b).count()))
+        .filter(|(_, n)| *n > 0)
+        .collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1));
+    println!("biomes:");
+    for (biome, n) in counts {
+        println!(
+            "  {:<24} {:>6.2}%",
+            format!("{:?}", biome),
+            100.0 * n as f64 / total
+        );
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let mut terrain = TiledWorldTerrain::from_config(&args.config);
+    if let Some(path) = &args.biomes {
+        terrain.set_biome_table(BiomeTable::load(path)?);
+    }
+    let mut world = TiledGameWorld::with_terrain(args.width, args.height, terrain);
+    if let Some(config) = &args.erosion {
+        world.erode(&args.seed, config);
+    }
+
+    if let Some(path) = &args.png {
+        let mut options = RenderOptions::new(
+            args.width as u32 * args.scale,
+            args.height as u32 * args.scale,
+        );
+        options.sampling = args.sampling;
+        options.orientation = args.orientation;
+        options.channel = args.channel;
+        render_world(&world, &args.seed, &options).save_png(path)?;
+    }
+
+    if args.csv.is_some() || args.json.is_some() || args.stats {
+        let cells: Vec<TerrainSample> = world.sample_region(&args.seed).samples().collect();
+        if let Some(path) = &args.csv {
+            write_csv(path, args.width, &cells)?;
+        }
+        if let Some(path) = &args.json {
+            write_json(path, &args.seed, args.width, args.height, &cells)?;
+        }
+        if args.stats {
+            let water_level = world.get_terrain().get_water_level();
+            print_stats(&args.seed, args.width, args.height, water_level, &cells);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("error: {}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+    if let Err(e) = run(args) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
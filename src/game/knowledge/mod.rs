@@ -0,0 +1,126 @@
+use crate::game::world::hex_map::{HexMap, HexMapShape};
+use crate::geometry::Hex;
+
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    /// Never seen; nothing is known about the hex.
+    Unexplored,
+    /// Seen before but not now; only the last snapshot is known.
+    Remembered,
+    /// Currently in view.
+    Visible,
+}
+
+/// What a nation last saw of a hex, and when.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Memory<T> {
+    pub snapshot: T,
+    pub turn: u64,
+}
+
+/// One nation's view of the world: which hexes it can see now, and what it remembers of the
+/// hexes it has explored. The contents of remembered hexes stay as they were last seen, however
+/// the world has changed since.
+pub struct Knowledge<T> {
+    memory: HexMap<Option<Memory<T>>>,
+    visible: HashSet<Hex>,
+}
+
+impl<T> Knowledge<T> {
+    pub fn new(shape: HexMapShape) -> Knowledge<T> {
+        Knowledge {
+            memory: HexMap::from_fn(shape, |_| None),
+            visible: HashSet::new(),
+        }
+    }
+
+    pub fn get_shape(&self) -> HexMapShape {
+        self.memory.get_shape()
+    }
+
+    pub fn get_visibility(&self, hex: &Hex) -> Visibility {
+        if self.visible.contains(hex) {
+            Visibility::Visible
+        } else if self.get_memory(hex).is_some() {
+            Visibility::Remembered
+        } else {
+            Visibility::Unexplored
+        }
+    }
+
+    pub fn get_memory(&self, hex: &Hex) -> Option<&Memory<T>> {
+        self.memory.get(hex).and_then(|m| m.as_ref())
+    }
+
+    pub fn get_visible(&self) -> &HashSet<Hex> {
+        &self.visible
+    }
+
+    /// Number of hexes that have been seen at least once.
+    pub fn get_explored(&self) -> usize {
+        self.memory.values().filter(|m| m.is_some()).count()
+    }
+
+    /// Replaces the set of visible hexes, as found by `vision::field_of_view` for all of the
+    /// nation's observers, and snapshots their contents. Hexes outside the shape are ignored and
+    /// hexes that drop out of view keep their last snapshot.
+    pub fn see<F: FnMut(&Hex) -> T>(&mut self, visible: HashSet<Hex>, turn: u64, mut contents: F) {
+        self.visible = visible
+            .into_iter()
+            .filter(|hex| self.memory.contains(hex))
+            .collect();
+        for hex in self.visible.iter() {
+            self.memory.set(
+                hex,
+                Some(Memory {
+                    snapshot: contents(hex),
+                    turn: turn,
+                }),
+            );
+        }
+    }
+
+    /// Stops seeing everything without forgetting any of it.
+    pub fn hide_all(&mut self) {
+        self.visible.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn remembers_what_was_last_seen() {
+        let mut knowledge = Knowledge::new(HexMapShape::Hexagon {
+            centre: Hex::new(0, 0, 0),
+            radius: 3,
+        });
+        let a = Hex::new(0, 0, 0);
+        let b = Hex::new(1, 0, -1);
+        let outside = Hex::new(4, 0, -4);
+        assert_eq!(knowledge.get_visibility(&a), Visibility::Unexplored);
+
+        knowledge.see([a, b, outside].iter().cloned().collect(), 1, |_| "first");
+        assert_eq!(knowledge.get_visibility(&b), Visibility::Visible);
+        assert_eq!(knowledge.get_visibility(&outside), Visibility::Unexplored);
+        assert_eq!(knowledge.get_visible().len(), 2);
+
+        knowledge.see([a].iter().cloned().collect(), 2, |_| "second");
+        assert_eq!(knowledge.get_visibility(&b), Visibility::Remembered);
+        assert_eq!(
+            knowledge.get_memory(&b),
+            Some(&Memory {
+                snapshot: "first",
+                turn: 1
+            })
+        );
+        assert_eq!(knowledge.get_memory(&a).unwrap().snapshot, "second");
+        assert_eq!(knowledge.get_explored(), 2);
+
+        knowledge.hide_all();
+        assert_eq!(knowledge.get_visibility(&a), Visibility::Remembered);
+    }
+}
@@ -1,2 +1,5 @@
+pub mod knowledge;
 pub mod object;
+pub mod pathfinding;
+pub mod vision;
 pub mod world;
\ No newline at end of file
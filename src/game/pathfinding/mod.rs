@@ -0,0 +1,348 @@
+use crate::game::world::hex_map::HexMap;
+use crate::game::world::Wrapped;
+use crate::geometry::{Coord, Hex};
+use crate::procgen::terrain::{MapBiome, TerrainSample};
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+pub trait MovementCost {
+    /// Cost of stepping from `from` into the adjacent hex `to`, or `None` if `to` cannot be
+    /// entered.
+    fn step_cost(&self, from: &Hex, to: &Hex) -> Option<u32>;
+
+    /// A lower bound on `step_cost`, which keeps the A* heuristic from overestimating.
+    fn min_step_cost(&self) -> u32 {
+        1
+    }
+
+    /// The hex that a neighbour stands for, which differs on worlds whose edges wrap around.
+    fn wrap(&self, hex: &Hex) -> Hex {
+        *hex
+    }
+
+    /// Number of steps between two hexes, for the A* heuristic.
+    fn distance(&self, a: &Hex, b: &Hex) -> isize {
+        a.distance(b)
+    }
+}
+
+impl<F: Fn(&Hex, &Hex) -> Option<u32>> MovementCost for F {
+    fn step_cost(&self, from: &Hex, to: &Hex) -> Option<u32> {
+        self(from, to)
+    }
+}
+
+impl<C: MovementCost> MovementCost for Wrapped<C> {
+    fn step_cost(&self, from: &Hex, to: &Hex) -> Option<u32> {
+        self.inner.step_cost(from, to)
+    }
+
+    fn min_step_cost(&self) -> u32 {
+        self.inner.min_step_cost()
+    }
+
+    fn wrap(&self, hex: &Hex) -> Hex {
+        self.grid.wrap(hex)
+    }
+
+    fn distance(&self, a: &Hex, b: &Hex) -> isize {
+        self.grid.distance(a, b)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnitKind {
+    Land,
+    Naval,
+}
+
+/// The default movement rules: land units are slowed by forests and mountains and cannot enter
+/// open water, naval units can only enter open water.
+pub fn biome_cost(biome: MapBiome, kind: UnitKind) -> Option<u32> {
+    match (kind, biome) {
+        (_, MapBiome::Empty) => None,
+        (UnitKind::Naval, MapBiome::Water) => Some(1),
+        (UnitKind::Naval, _) => None,
+        (UnitKind::Land, MapBiome::Water) => None,
+        (UnitKind::Land, MapBiome::Ice) => Some(3),
+        (UnitKind::Land, MapBiome::Mountain) => Some(5),
+        (UnitKind::Land, MapBiome::BorealForest)
+        | (UnitKind::Land, MapBiome::TemperateRainforest)
+        | (UnitKind::Land, MapBiome::TemperateSeasonalForest)
+        | (UnitKind::Land, MapBiome::TropicalRainforest)
+        | (UnitKind::Land, MapBiome::TropicalSeasonalForest) => Some(2),
+        (UnitKind::Land, MapBiome::Tundra)
+        | (UnitKind::Land, MapBiome::Shrubland)
+        | (UnitKind::Land, MapBiome::TemperateGrassland)
+        | (UnitKind::Land, MapBiome::Savannah)
+        | (UnitKind::Land, MapBiome::Desert) => Some(1),
+    }
+}
+
+/// Movement over stored tiles using `biome_cost`; hexes outside the map cannot be entered.
+pub struct BiomeMovementCost<'a> {
+    pub tiles: &'a HexMap<TerrainSample>,
+    pub kind: UnitKind,
+}
+
+impl<'a> MovementCost for BiomeMovementCost<'a> {
+    fn step_cost(&self, _from: &Hex, to: &Hex) -> Option<u32> {
+        self.tiles
+            .get(to)
+            .and_then(|tile| biome_cost(tile.biome, self.kind))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    /// Every hex along the path, including the start and the goal.
+    pub hexes: Vec<Hex>,
+    pub cost: u32,
+}
+
+#[derive(PartialEq, Eq)]
+struct Node {
+    estimate: u32,
+    cost: u32,
+    hex: Hex,
+}
+
+impl Ord for Node {
+    // BinaryHeap is a max-heap, so order by lowest estimate first, then prefer nodes further
+    // along, which are more likely to be on the final path
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then(self.cost.cmp(&other.cost))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The cheapest path from `start` to `goal` costing at most `max_cost`, found with A* using
+/// hex distance as the heuristic. The search only terminates on unreachable goals if `cost`
+/// bounds the searchable area or `max_cost` is finite.
+pub fn find_path<C: MovementCost>(
+    start: &Hex,
+    goal: &Hex,
+    max_cost: u32,
+    cost: &C,
+) -> Option<Path> {
+    let start = cost.wrap(start);
+    let goal = cost.wrap(goal);
+    let heuristic = |hex: &Hex| cost.distance(hex, &goal) as u32 * cost.min_step_cost();
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<Hex, u32> = HashMap::new();
+    let mut came_from: HashMap<Hex, Hex> = HashMap::new();
+    best.insert(start, 0);
+    open.push(Node {
+        estimate: heuristic(&start),
+        cost: 0,
+        hex: start,
+    });
+
+    while let Some(Node { cost: g, hex, .. }) = open.pop() {
+        if hex == goal {
+            let mut hexes = vec![hex];
+            while let Some(previous) = came_from.get(hexes.last().unwrap()) {
+                hexes.push(*previous);
+            }
+            hexes.reverse();
+            return Some(Path {
+                hexes: hexes,
+                cost: g,
+            });
+        }
+        if g > best[&hex] {
+            // a cheaper route to this hex was queued after this one
+            continue;
+        }
+        for next in hex.neighbours().iter().map(|n| cost.wrap(n)) {
+            let next = &next;
+            let step = match cost.step_cost(&hex, next) {
+                Some(step) => step,
+                None => continue,
+            };
+            let next_cost = g.saturating_add(step);
+            if next_cost > max_cost || best.get(next).is_some_and(|b| *b <= next_cost) {
+                continue;
+            }
+            best.insert(*next, next_cost);
+            came_from.insert(*next, hex);
+            open.push(Node {
+                estimate: next_cost.saturating_add(heuristic(next)),
+                cost: next_cost,
+                hex: *next,
+            });
+        }
+    }
+    None
+}
+
+/// The cheapest cost of reaching every hex that can be reached from `start` for at most
+/// `max_cost`, found with Dijkstra's algorithm. Useful for movement ranges and for reusing one
+/// search for many goals.
+pub fn reachable<C: MovementCost>(start: &Hex, max_cost: u32, cost: &C) -> HashMap<Hex, u32> {
+    let start = cost.wrap(start);
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<Hex, u32> = HashMap::new();
+    best.insert(start, 0);
+    open.push(Node {
+        estimate: 0,
+        cost: 0,
+        hex: start,
+    });
+
+    while let Some(Node { cost: g, hex, .. }) = open.pop() {
+        if g > best[&hex] {
+            continue;
+        }
+        for next in hex.neighbours().iter().map(|n| cost.wrap(n)) {
+            let next = &next;
+            let next_cost = match cost.step_cost(&hex, next) {
+                Some(step) => g.saturating_add(step),
+                None => continue,
+            };
+            if next_cost > max_cost || best.get(next).is_some_and(|b| *b <= next_cost) {
+                continue;
+            }
+            best.insert(*next, next_cost);
+            open.push(Node {
+                estimate: next_cost,
+                cost: next_cost,
+                hex: *next,
+            });
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::world::hex_map::HexMapShape;
+    use crate::geometry::{OffsetCoord, OffsetKind};
+
+    fn tiles(rows: &[&str]) -> HexMap<TerrainSample> {
+        let shape = HexMapShape::Rectangle {
+            width: rows[0].len(),
+            height: rows.len(),
+        };
+        let mut biomes = rows.iter().flat_map(|row| row.chars()).map(|c| match c {
+            '~' => MapBiome::Water,
+            '^' => MapBiome::Mountain,
+            'T' => MapBiome::BorealForest,
+            _ => MapBiome::TemperateGrassland,
+        });
+        HexMap::from_fn(shape, |_| TerrainSample {
+            height: 0.6,
+            temperature: 0.5,
+            precipitation: 0.5,
+            biome: biomes.next().unwrap(),
+        })
+    }
+
+    fn hex_at(col: isize, row: isize) -> Hex {
+        OffsetCoord::new(col, row).to_hex(OffsetKind::OddR)
+    }
+
+    #[test]
+    fn open_ground_follows_a_shortest_line() {
+        let free = |_: &Hex, _: &Hex| Some(1);
+        let start = Hex::new(0, 0, 0);
+        let goal = Hex::new(4, -7, 3);
+        let path = find_path(&start, &goal, 100, &free).unwrap();
+        assert_eq!(path.cost, 7);
+        assert_eq!(path.hexes.len(), 8);
+        assert_eq!(path.hexes[0], start);
+        assert_eq!(path.hexes[7], goal);
+        for pair in path.hexes.windows(2) {
+            assert_eq!(pair[0].distance(&pair[1]), 1);
+        }
+        assert_eq!(find_path(&start, &goal, 6, &free), None);
+    }
+
+    #[test]
+    fn land_units_go_around_water_and_mountains() {
+        let map = tiles(&[
+            "......", //
+            ".~~~^.", //
+            ".~~~^.", //
+            "......",
+        ]);
+        let land = BiomeMovementCost {
+            tiles: &map,
+            kind: UnitKind::Land,
+        };
+        let path = find_path(&hex_at(0, 1), &hex_at(5, 2), 100, &land).unwrap();
+        assert!(path
+            .hexes
+            .iter()
+            .all(|h| map[*h].biome == MapBiome::TemperateGrassland));
+        assert_eq!(path.cost, path.hexes.len() as u32 - 1);
+
+        let naval = BiomeMovementCost {
+            tiles: &map,
+            kind: UnitKind::Naval,
+        };
+        assert_eq!(find_path(&hex_at(1, 1), &hex_at(5, 2), 100, &naval), None);
+        assert_eq!(
+            find_path(&hex_at(1, 1), &hex_at(3, 2), 100, &naval)
+                .unwrap()
+                .cost,
+            2
+        );
+    }
+
+    #[test]
+    fn paths_cross_the_seam_of_wrapping_worlds() {
+        use crate::geometry::{WrapMode, WrappingGrid};
+        let map = tiles(&[
+            "......", //
+            "..~~..", //
+            "..~~..", //
+            "......",
+        ]);
+        let land = BiomeMovementCost {
+            tiles: &map,
+            kind: UnitKind::Land,
+        };
+        let bounded = find_path(&hex_at(0, 1), &hex_at(5, 2), 100, &land).unwrap();
+        let wrapped = Wrapped {
+            grid: WrappingGrid::new(OffsetKind::OddR, 6, 4, WrapMode::Cylinder),
+            inner: land,
+        };
+        let path = find_path(&hex_at(0, 1), &hex_at(5, 2), 100, &wrapped).unwrap();
+        assert!(path.cost < bounded.cost);
+        assert_eq!(path.cost, 2);
+        assert!(path.hexes.iter().all(|h| map.contains(h)));
+        let range = reachable(&hex_at(0, 0), 1, &wrapped);
+        assert!(range.contains_key(&hex_at(5, 0)));
+    }
+
+    #[test]
+    fn forests_cost_more() {
+        let map = tiles(&["TTT", "...", "TTT"]);
+        let land = BiomeMovementCost {
+            tiles: &map,
+            kind: UnitKind::Land,
+        };
+        let costs = reachable(&hex_at(0, 1), 2, &land);
+        assert_eq!(costs[&hex_at(2, 1)], 2);
+        assert_eq!(costs[&hex_at(0, 0)], 2);
+        assert_eq!(costs[&hex_at(1, 0)], 2);
+        assert!(costs.values().all(|c| *c <= 2));
+        assert_eq!(
+            find_path(&hex_at(0, 0), &hex_at(2, 0), 100, &land)
+                .unwrap()
+                .cost,
+            4
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use crate::game::world::hex_map::HexMap;
+use crate::game::world::Wrapped;
+use crate::geometry::{Coord, Hex, HexRingIterator};
+use crate::procgen::terrain::{MapBiome, TerrainSample};
+
+use std::collections::HashSet;
+
+/// How a hex looks to an observer. Heights are measured in hex widths, so that they compare
+/// directly with distances along the ground.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sight {
+    /// Height of the ground, or of the surface for water.
+    pub ground: f64,
+    /// Height of whatever stands on the ground and blocks the view past it, such as trees.
+    pub cover: f64,
+}
+
+impl Sight {
+    pub fn get_top(&self) -> f64 {
+        self.ground + self.cover
+    }
+}
+
+pub trait SightQuery {
+    /// `None` for hexes that can never be seen, such as those outside the map.
+    fn sight(&self, hex: &Hex) -> Option<Sight>;
+
+    /// The hex that a hex in view stands for, which differs on worlds whose edges wrap around.
+    fn wrap(&self, hex: &Hex) -> Hex {
+        *hex
+    }
+}
+
+impl<F: Fn(&Hex) -> Option<Sight>> SightQuery for F {
+    fn sight(&self, hex: &Hex) -> Option<Sight> {
+        self(hex)
+    }
+}
+
+impl<Q: SightQuery> SightQuery for Wrapped<Q> {
+    fn sight(&self, hex: &Hex) -> Option<Sight> {
+        self.inner.sight(&self.grid.wrap(hex))
+    }
+
+    fn wrap(&self, hex: &Hex) -> Hex {
+        self.grid.wrap(hex)
+    }
+}
+
+/// Where `TerrainSight` finds the terrain of a hex: a map of stored tiles, or a function
+/// that samples them as they are asked for.
+pub trait TileSource {
+    /// `None` for hexes outside the world.
+    fn get_tile(&self, hex: &Hex) -> Option<TerrainSample>;
+}
+
+impl TileSource for &HexMap<TerrainSample> {
+    fn get_tile(&self, hex: &Hex) -> Option<TerrainSample> {
+        self.get(hex).copied()
+    }
+}
+
+impl<F: Fn(&Hex) -> Option<TerrainSample>> TileSource for F {
+    fn get_tile(&self, hex: &Hex) -> Option<TerrainSample> {
+        self(hex)
+    }
+}
+
+/// Sight over terrain tiles. Water is seen at sea level, forests add cover and mountains stand
+/// above the already high ground they are on.
+pub struct TerrainSight<T> {
+    pub tiles: T,
+    pub water_level: f64,
+    /// Hex widths per unit of terrain height.
+    pub vertical_scale: f64,
+    pub forest_cover: f64,
+    pub mountain_cover: f64,
+}
+
+impl<T: TileSource> TerrainSight<T> {
+    pub fn new(tiles: T, water_level: f64) -> TerrainSight<T> {
+        TerrainSight {
+            tiles: tiles,
+            water_level: water_level,
+            vertical_scale: 20.0,
+            forest_cover: 1.0,
+            mountain_cover: 2.0,
+        }
+    }
+
+    /// `base` extended by one hex for every hex width the observer stands above the sea.
+    pub fn sight_radius(&self, observer: &Hex, base: isize) -> isize {
+        match self.sight(observer) {
+            Some(sight) => {
+                let sea = self.water_level * self.vertical_scale;
+                base + (sight.ground - sea).max(0.0).floor() as isize
+            }
+            None => base,
+        }
+    }
+}
+
+impl<T: TileSource> SightQuery for TerrainSight<T> {
+    fn sight(&self, hex: &Hex) -> Option<Sight> {
+        self.tiles.get_tile(hex).map(|tile| {
+            let cover = match tile.biome {
+                MapBiome::BorealForest
+                | MapBiome::TemperateRainforest
+                | MapBiome::TemperateSeasonalForest
+                | MapBiome::TropicalRainforest
+                | MapBiome::TropicalSeasonalForest => self.forest_cover,
+                MapBiome::Mountain => self.mountain_cover,
+                _ => 0.0,
+            };
+            Sight {
+                ground: tile.height.max(self.water_level) * self.vertical_scale,
+                cover: cover,
+            }
+        })
+    }
+}
+
+/// The part of the view hidden behind a hex: everything within its arc whose top does not rise
+/// above `slope` as seen from the observer.
+struct Shadow {
+    start: f64,
+    end: f64,
+    slope: f64,
+}
+
+/// The steepest shadow in every direction, with overlapping shadows merged into their maximum.
+/// Piece `j` covers the arc from `pieces[j].0` to the start of the next piece, or to the end of
+/// the turn for the last one, and `pieces[j].1` is its slope.
+struct Horizon {
+    pieces: Vec<(f64, f64)>,
+}
+
+impl Horizon {
+    fn new() -> Horizon {
+        Horizon {
+            pieces: vec![(0.0, f64::NEG_INFINITY)],
+        }
+    }
+
+    /// The slope a hex whose centre is at `a` must reach to be seen. Arcs include their ends, so
+    /// at the boundary between two pieces the steeper one counts.
+    fn get(&self, a: f64) -> f64 {
+        let j = self.pieces.partition_point(|p| p.0 <= a) - 1;
+        let (start, slope) = self.pieces[j];
+        if start == a {
+            let before = if j == 0 { self.pieces.len() - 1 } else { j - 1 };
+            slope.max(self.pieces[before].1)
+        } else {
+            slope
+        }
+    }
+
+    /// Raises the horizon to the shadows of one ring, which are in order and do not overlap.
+    fn raise(&mut self, cast: &[Shadow]) {
+        let mut arcs = Vec::with_capacity(cast.len() + 1);
+        for shadow in cast {
+            // only the first hex of a ring crosses the start of the turn
+            if shadow.start < 0.0 {
+                arcs.push((shadow.start + 1.0, 1.0, shadow.slope));
+                arcs.push((0.0, shadow.end, shadow.slope));
+            } else {
+                arcs.push((shadow.start, shadow.end, shadow.slope));
+            }
+        }
+        arcs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut cuts: Vec<f64> = self.pieces.iter().map(|p| p.0).collect();
+        cuts.extend(arcs.iter().flat_map(|a| [a.0, a.1]));
+        cuts.push(1.0);
+        cuts.sort_by(|a, b| a.total_cmp(b));
+        cuts.dedup();
+
+        let mut pieces: Vec<(f64, f64)> = Vec::with_capacity(cuts.len());
+        for cut in cuts.windows(2) {
+            let middle = (cut[0] + cut[1]) / 2.0;
+            let old = self.pieces[self.pieces.partition_point(|p| p.0 <= middle) - 1].1;
+            let new = match arcs.partition_point(|a| a.0 <= middle) {
+                0 => f64::NEG_INFINITY,
+                i if middle <= arcs[i - 1].1 => arcs[i - 1].2,
+                _ => f64::NEG_INFINITY,
+            };
+            let slope = old.max(new);
+            match pieces.last() {
+                Some(last) if last.1 == slope => {}
+                _ => pieces.push((cut[0], slope)),
+            }
+        }
+        self.pieces = pieces;
+    }
+}
+
+/// The hexes within `radius` of `observer` that can be seen from `eye_height` above the
+/// observer's ground, as wrapped by the query.
+///
+/// Rings are cast outwards one at a time. Each ring is walked from the same corner, so the
+/// `i`th of the `6r` hexes of ring `r` covers the arc `(i - 0.5) / 6r .. (i + 0.5) / 6r` of the
+/// full turn. A hex is visible if its top rises at least as steeply from the eye as every
+/// nearer hex casting a shadow over its centre, and it then casts a shadow of its own, so that
+/// tall cover hides low ground behind it while an observer on a peak sees over it.
+pub fn field_of_view<Q: SightQuery>(
+    observer: &Hex,
+    radius: isize,
+    eye_height: f64,
+    query: &Q,
+) -> HashSet<Hex> {
+    let mut visible = HashSet::new();
+    cast(
+        observer,
+        radius,
+        eye_height,
+        query,
+        |_, _| true,
+        |hex| {
+            visible.insert(query.wrap(&hex));
+        },
+    );
+    visible
+}
+
+/// Casts rings out to `radius` as `field_of_view` describes, but only through the hexes of ring
+/// `r` for which `walk(r, centre)` holds, and calls `seen` with each of those that is visible.
+fn cast<Q: SightQuery>(
+    observer: &Hex,
+    radius: isize,
+    eye_height: f64,
+    query: &Q,
+    walk: impl Fn(isize, f64) -> bool,
+    mut seen: impl FnMut(Hex),
+) {
+    let eye = match query.sight(observer) {
+        Some(sight) => sight.ground + eye_height,
+        None => return,
+    };
+    seen(*observer);
+
+    let mut horizon = Horizon::new();
+    for r in 1..=radius {
+        let hexes = 6 * r as usize;
+        let mut cast = Vec::new();
+        for (i, hex) in HexRingIterator::new(*observer, r).enumerate() {
+            let centre = i as f64 / hexes as f64;
+            if !walk(r, centre) {
+                continue;
+            }
+            let sight = match query.sight(&hex) {
+                Some(sight) => sight,
+                None => continue,
+            };
+            let slope = (sight.get_top() - eye) / r as f64;
+            if slope + 1e-9 >= horizon.get(centre) {
+                seen(hex);
+                cast.push(Shadow {
+                    start: (i as f64 - 0.5) / hexes as f64,
+                    end: (i as f64 + 0.5) / hexes as f64,
+                    slope: slope,
+                });
+            }
+        }
+        // a ring only shadows the rings beyond it
+        horizon.raise(&cast);
+    }
+}
+
+/// Whether `target` can be seen from `observer`. On worlds whose edges wrap around, `target`
+/// is the copy of the hex to look at, as placed relative to the observer.
+///
+/// This walks only the hexes that `field_of_view` would consult on the way to `target`. The
+/// target is hidden only by hexes of nearer rings whose arcs cover its centre, and those only
+/// by hexes whose arcs cover their centres, so ring `r` needs the hexes whose centres lie within
+/// `1 / 12k` of the target's direction for each ring `k` from `r` up to the target's.
+pub fn line_of_sight<Q: SightQuery>(
+    observer: &Hex,
+    target: &Hex,
+    eye_height: f64,
+    query: &Q,
+) -> bool {
+    let distance = observer.distance(target);
+    if distance == 0 {
+        return query.sight(observer).is_some();
+    }
+    let direction = match HexRingIterator::new(*observer, distance).position(|h| h == *target) {
+        Some(i) => i as f64 / (6 * distance) as f64,
+        None => return false,
+    };
+    // `reach[r]` is how far from `direction` the centres of ring `r` may be
+    let mut reach = vec![0.0; distance as usize + 1];
+    for r in (1..distance).rev() {
+        reach[r as usize] = reach[r as usize + 1] + 1.0 / (12 * r) as f64;
+    }
+
+    let mut visible = false;
+    let walk = |r: isize, centre: f64| {
+        let apart = (centre - direction).abs();
+        apart.min(1.0 - apart) <= reach[r as usize] + 1e-9
+    };
+    cast(observer, distance, eye_height, query, walk, |hex| {
+        visible |= hex == *target;
+    });
+    visible
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::geometry::HexSpiralIterator;
+
+    fn flat_with(blockers: Vec<(Hex, f64)>) -> impl Fn(&Hex) -> Option<Sight> {
+        move |hex| {
+            let cover = blockers
+                .iter()
+                .find(|(h, _)| h == hex)
+                .map_or(0.0, |(_, c)| *c);
+            Some(Sight {
+                ground: 0.0,
+                cover: cover,
+            })
+        }
+    }
+
+    #[test]
+    fn open_ground_is_all_visible() {
+        let origin = Hex::new(0, 0, 0);
+        let visible = field_of_view(&origin, 6, 0.5, &flat_with(vec![]));
+        assert_eq!(visible.len(), HexSpiralIterator::new(origin, 6).count());
+    }
+
+    #[test]
+    fn cover_casts_shadows_that_height_overcomes() {
+        let origin = Hex::new(0, 0, 0);
+        let wall = Hex::new(2, 0, -2);
+        let query = flat_with(vec![(wall, 2.0)]);
+        let visible = field_of_view(&origin, 8, 0.5, &query);
+        assert!(visible.contains(&wall));
+        for d in 3..=8 {
+            assert!(!visible.contains(&Hex::new(d, 0, -d)), "{}", d);
+        }
+        assert!(visible.contains(&Hex::new(4, -4, 0)));
+        assert!(!line_of_sight(&origin, &Hex::new(5, 0, -5), 0.5, &query));
+
+        // an observer high above the wall sees straight past it
+        let visible = field_of_view(&origin, 8, 10.0, &query);
+        assert!(visible.contains(&Hex::new(8, 0, -8)));
+    }
+
+    #[test]
+    fn terrain_sight_rules() {
+        use crate::game::world::hex_map::HexMapShape;
+        let shape = HexMapShape::Hexagon {
+            centre: Hex::new(0, 0, 0),
+            radius: 6,
+        };
+        let tiles = HexMap::from_fn(shape, |hex| TerrainSample {
+            height: if hex.distance(&Hex::new(0, 0, 0)) == 0 {
+                0.8
+            } else {
+                0.6
+            },
+            temperature: 0.5,
+            precipitation: 0.5,
+            biome: if hex.distance(&Hex::new(0, 0, 0)) == 1 {
+                MapBiome::BorealForest
+            } else {
+                MapBiome::TemperateGrassland
+            },
+        });
+        let sight = TerrainSight::new(&tiles, 0.55);
+        let origin = Hex::new(0, 0, 0);
+        assert_eq!(sight.sight_radius(&origin, 3), 8);
+        assert_eq!(sight.sight(&Hex::new(7, 0, -7)), None);
+
+        // standing on the hill, the forest ring below does not block the plain
+        let visible = field_of_view(&origin, 6, 0.1, &sight);
+        assert_eq!(visible.len(), shape.len());
+
+        // tiles looked up as they are needed are seen the same as stored ones
+        let lookup = TerrainSight::new(|hex: &Hex| tiles.get(hex).copied(), 0.55);
+        assert_eq!(field_of_view(&origin, 6, 0.1, &lookup), visible);
+    }
+
+    #[test]
+    fn line_of_sight_agrees_with_field_of_view() {
+        use std::cell::Cell;
+        let origin = Hex::new(0, 0, 0);
+        let queries = Cell::new(0);
+        let query = |hex: &Hex| {
+            queries.set(queries.get() + 1);
+            let ground = ((hex.x * 7 + hex.y * 13).rem_euclid(5)) as f64 * 0.3;
+            let cover = if (hex.x - 2 * hex.y).rem_euclid(7) == 0 {
+                1.5
+            } else {
+                0.0
+            };
+            Some(Sight {
+                ground: ground,
+                cover: cover,
+            })
+        };
+        let visible = field_of_view(&origin, 10, 0.5, &query);
+        assert!(visible.len() < HexSpiralIterator::new(origin, 10).count());
+
+        let full = queries.replace(0);
+        for target in HexRingIterator::new(origin, 10) {
+            assert_eq!(
+                line_of_sight(&origin, &target, 0.5, &query),
+                visible.contains(&target),
+                "{:?}",
+                target
+            );
+        }
+        assert!(queries.get() < full * 60 / 4, "{} {}", queries.get(), full);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::geometry::{Hex, OffsetCoord, OffsetKind};
+use crate::procgen::procseed::ProcSeed;
+
+use std::collections::HashMap;
+use std::mem;
+
+/// Values generated for square chunks of an `OffsetKind::OddR` grid on first access, with the
+/// least recently used chunks dropped once the cache outgrows its memory budget. Every value
+/// depends on the seed, so the whole cache is cleared when asked for a different seed or skew,
+/// and on the world, so it is also cleared when given a different world generation.
+pub struct ChunkCache<T> {
+    chunk_size: usize,
+    capacity: usize,
+    seed: Option<ProcSeed>,
+    generation: u64,
+    chunks: HashMap<(isize, isize), Chunk<T>>,
+    clock: u64,
+}
+
+struct Chunk<T> {
+    values: Vec<T>,
+    last_used: u64,
+}
+
+impl<T> ChunkCache<T> {
+    /// Chunks are `chunk_size` hexes square, and as many are kept as fit in `max_bytes`, but
+    /// never fewer than one.
+    pub fn new(chunk_size: usize, max_bytes: usize) -> ChunkCache<T> {
+        let chunk_bytes = chunk_size * chunk_size * mem::size_of::<T>().max(1);
+        ChunkCache {
+            chunk_size: chunk_size,
+            capacity: (max_bytes / chunk_bytes).max(1),
+            seed: None,
+            generation: 0,
+            chunks: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn get_chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Maximum number of chunks kept.
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of chunks currently kept.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn invalidate(&mut self) {
+        self.chunks.clear();
+        self.seed = None;
+    }
+
+    /// Clears the cache if it was filled from another generation of the world, as counted by
+    /// `TiledGameWorld::get_generation`.
+    pub fn set_generation(&mut self, generation: u64) {
+        if self.generation != generation {
+            self.invalidate();
+            self.generation = generation;
+        }
+    }
+
+    /// The value at `hex` for `seed`, generating the whole chunk containing it with `generate`
+    /// if it is not cached.
+    pub fn get<F: FnMut(&Hex) -> T>(&mut self, seed: &ProcSeed, hex: &Hex, mut generate: F) -> &T {
+        if self.seed.as_ref() != Some(seed) {
+            self.invalidate();
+            self.seed = Some(seed.clone());
+        }
+        let size = self.chunk_size as isize;
+        let offset = OffsetCoord::from_hex(hex, OffsetKind::OddR);
+        let key = (offset.col.div_euclid(size), offset.row.div_euclid(size));
+        let index = (offset.row.rem_euclid(size) * size + offset.col.rem_euclid(size)) as usize;
+
+        self.clock += 1;
+        if !self.chunks.contains_key(&key) {
+            if self.chunks.len() >= self.capacity {
+                self.evict();
+            }
+            let mut values = Vec::with_capacity(self.chunk_size * self.chunk_size);
+            for row in 0..size {
+                for col in 0..size {
+                    let offset = OffsetCoord::new(key.0 * size + col, key.1 * size + row);
+                    values.push(generate(&offset.to_hex(OffsetKind::OddR)));
+                }
+            }
+            self.chunks.insert(
+                key,
+                Chunk {
+                    values: values,
+                    last_used: 0,
+                },
+            );
+        }
+        let chunk = self.chunks.get_mut(&key).unwrap();
+        chunk.last_used = self.clock;
+        &chunk.values[index]
+    }
+
+    fn evict(&mut self) {
+        let oldest = self
+            .chunks
+            .iter()
+            .min_by_key(|(_, chunk)| chunk.last_used)
+            .map(|(key, _)| *key);
+        if let Some(key) = oldest {
+            self.chunks.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn generates_whole_chunks_once() {
+        let calls = Cell::new(0);
+        let generate = |hex: &Hex| {
+            calls.set(calls.get() + 1);
+            hex.x * 1000 + hex.y
+        };
+        let seed = ProcSeed::new(&0u32, 0.0);
+        let mut cache = ChunkCache::new(4, 2 * 16 * mem::size_of::<isize>());
+        assert_eq!(cache.get_capacity(), 2);
+
+        let h = OffsetCoord::new(5, 6).to_hex(OffsetKind::OddR);
+        assert_eq!(*cache.get(&seed, &h, generate), h.x * 1000 + h.y);
+        assert_eq!(calls.get(), 16);
+        let near = OffsetCoord::new(7, 4).to_hex(OffsetKind::OddR);
+        assert_eq!(*cache.get(&seed, &near, generate), near.x * 1000 + near.y);
+        assert_eq!(calls.get(), 16);
+
+        // negative coordinates fall into their own chunks
+        let far = OffsetCoord::new(-1, -1).to_hex(OffsetKind::OddR);
+        assert_eq!(*cache.get(&seed, &far, generate), far.x * 1000 + far.y);
+        assert_eq!(calls.get(), 32);
+        assert_eq!(cache.len(), 2);
+
+        // the chunk of `h` is the least recently used, so it goes first
+        let third = OffsetCoord::new(9, 0).to_hex(OffsetKind::OddR);
+        cache.get(&seed, &third, generate);
+        assert_eq!(cache.len(), 2);
+        cache.get(&seed, &far, generate);
+        assert_eq!(calls.get(), 48);
+        cache.get(&seed, &h, generate);
+        assert_eq!(calls.get(), 64);
+    }
+
+    #[test]
+    fn new_seeds_invalidate() {
+        let mut cache = ChunkCache::new(2, 1 << 20);
+        let h = Hex::new(0, 0, 0);
+        let mut seed = ProcSeed::new(&0u32, 0.0);
+        assert_eq!(*cache.get(&seed, &h, |_| 1), 1);
+        assert_eq!(*cache.get(&seed, &h, |_| 2), 1);
+        seed.skew += 0.5;
+        assert_eq!(*cache.get(&seed, &h, |_| 3), 3);
+        assert_eq!(*cache.get(&ProcSeed::new(&1u32, 0.5), &h, |_| 4), 4);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,378 @@
+use super::hex_map::HexMap;
+use crate::geometry::WrappingGrid;
+use crate::procgen::procseed::ProcSeed;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Parameters of `erode`. Heights and slopes are in terrain height units per tile, so the
+/// defaults suit worlds whose neighbouring tiles differ by around a hundredth.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErosionConfig {
+    /// Rounds of erosion, each dropping one droplet per tile followed by one thermal pass.
+    pub iterations: usize,
+    /// Steps a droplet takes before it evaporates.
+    pub droplet_lifetime: usize,
+    /// Sediment a droplet can carry per unit of slope, speed and water.
+    pub capacity: f64,
+    /// Slope capacity is reckoned from on flat ground, so that droplets still carry sediment
+    /// across plains.
+    pub min_slope: f64,
+    /// Fraction of its spare capacity a droplet picks up from each tile.
+    pub erosion: f64,
+    /// Fraction of its excess sediment a droplet drops on each tile.
+    pub deposition: f64,
+    /// Fraction of a droplet's water lost with each step.
+    pub evaporation: f64,
+    /// How quickly droplets speed up running downhill.
+    pub gravity: f64,
+    /// Steepest difference in height between neighbours that thermal passes leave alone.
+    pub talus: f64,
+    /// Fraction of the excess over `talus` that slumps downhill in each thermal pass.
+    pub thermal_rate: f64,
+}
+
+impl ErosionConfig {
+    pub fn from_ron(text: &str) -> Result<ErosionConfig, Box<dyn Error>> {
+        Ok(ron::de::from_str(text)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ErosionConfig, Box<dyn Error>> {
+        ErosionConfig::from_ron(&fs::read_to_string(path)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String, Box<dyn Error>> {
+        Ok(ron::ser::to_string_pretty(self, Default::default())?)
+    }
+}
+
+impl Default for ErosionConfig {
+    fn default() -> ErosionConfig {
+        ErosionConfig {
+            iterations: 8,
+            droplet_lifetime: 40,
+            capacity: 2.0,
+            min_slope: 0.001,
+            erosion: 0.05,
+            deposition: 0.1,
+            evaporation: 0.03,
+            gravity: 4.0,
+            talus: 0.012,
+            thermal_rate: 0.5,
+        }
+    }
+}
+
+/// Hydraulic erosion with thermal slumping over `heights`, whose tiles are neighbours as in
+/// `grid`. Droplets run down the steepest way from tile to tile, picking up sediment where they
+/// speed up and dropping it where they slow down, fill pits or reach the sea below `sea_level`,
+/// which carves valleys and leaves fans and deltas where they open out. Thermal passes then
+/// let slopes steeper than `config.talus` slump, which widens the channels into valleys and
+/// wears ridges down. Material only moves, so the total height is kept, except for sediment
+/// carried out to sea that could not settle without building the seabed up to `sea_level`.
+///
+/// Where droplets start is taken from `seed` and everything else runs in a fixed order, so the
+/// result depends on nothing but the inputs.
+pub fn erode(
+    heights: &mut HexMap<f64>,
+    grid: &WrappingGrid,
+    seed: &ProcSeed,
+    config: &ErosionConfig,
+    sea_level: f64,
+) {
+    let shape = heights.get_shape();
+    let neighbours: Vec<Vec<usize>> = shape
+        .hexes()
+        .map(|hex| {
+            grid.neighbours(&hex)
+                .iter()
+                .filter_map(|n| shape.index(n))
+                .collect()
+        })
+        .collect();
+    let mut cells: Vec<f64> = heights.values().cloned().collect();
+    if cells.is_empty() {
+        return;
+    }
+
+    let seed = seed.derive(&"erosion");
+    for round in 0..config.iterations {
+        let round_seed = seed.derive(&round);
+        for k in 0..cells.len() {
+            let start = (round_seed.derive(&k).get() % cells.len() as u64) as usize;
+            run_droplet(&mut cells, &neighbours, start, config, sea_level);
+        }
+        slump(&mut cells, &neighbours, config);
+    }
+
+    for ((_, height), eroded) in heights.iter_mut().zip(cells) {
+        *height = eroded;
+    }
+}
+
+fn run_droplet(
+    cells: &mut [f64],
+    neighbours: &[Vec<usize>],
+    start: usize,
+    config: &ErosionConfig,
+    sea_level: f64,
+) {
+    let mut cell = start;
+    let mut speed = 1.0;
+    let mut water = 1.0;
+    let mut sediment: f64 = 0.0;
+    for _ in 0..config.droplet_lifetime {
+        let next = match neighbours[cell]
+            .iter()
+            .min_by(|a, b| cells[**a].total_cmp(&cells[**b]))
+        {
+            Some(next) => *next,
+            None => break,
+        };
+        let drop = cells[cell] - cells[next];
+        if drop <= 0.0 {
+            // a pit: fill it up towards the lowest way out, and pool there if that runs dry
+            let fill = sediment.min(-drop);
+            cells[cell] += fill;
+            sediment -= fill;
+            if sediment <= 0.0 {
+                break;
+            }
+        } else if cells[cell] < sea_level {
+            // water stalls in the sea and drops its sediment along the seabed, but never
+            // builds it up to the waterline, so that coasts stay about where they were
+            let amount = (sediment * config.deposition).min((sea_level - cells[cell]) / 2.0);
+            cells[cell] += amount;
+            sediment -= amount;
+        } else {
+            let capacity = drop.max(config.min_slope) * speed * water * config.capacity;
+            if sediment > capacity {
+                let amount = (sediment - capacity) * config.deposition;
+                cells[cell] += amount;
+                sediment -= amount;
+            } else {
+                let amount = ((capacity - sediment) * config.erosion).min(drop);
+                sediment += wear(cells, &neighbours[cell], cell, cells[next], amount);
+            }
+            speed = (speed * speed + drop * config.gravity).sqrt();
+        }
+        water *= 1.0 - config.evaporation;
+        cell = next;
+    }
+    // what is still carried at sea is washed out into the deep
+    if cells[cell] >= sea_level {
+        cells[cell] += sediment;
+    }
+}
+
+/// Wears up to `amount` off `cell` and the tiles around it, half from `cell` itself, so that
+/// droplets cut smooth channels rather than single tile pits. No tile is worn below `floor`,
+/// the height of the tile the droplet runs on to, or it would leave a pit behind. Returns how
+/// much was worn away.
+fn wear(cells: &mut [f64], around: &[usize], cell: usize, floor: f64, amount: f64) -> f64 {
+    let mut worn = 0.0;
+    let share = amount / 2.0 / around.len() as f64;
+    for (i, share) in around
+        .iter()
+        .map(|i| (*i, share))
+        .chain(std::iter::once((cell, amount / 2.0)))
+    {
+        let taken = share.min(cells[i] - floor).max(0.0);
+        cells[i] -= taken;
+        worn += taken;
+    }
+    worn
+}
+
+/// One thermal pass. Every tile's change is worked out from the heights before the pass, so the
+/// order tiles are visited in does not matter and the pass can run in parallel.
+fn slump(cells: &mut Vec<f64>, neighbours: &[Vec<usize>], config: &ErosionConfig) {
+    let flow =
+        |from: f64, to: f64| (from - to - config.talus).max(0.0) * config.thermal_rate / 2.0 / 6.0;
+    let slumped: Vec<f64> = (0..cells.len())
+        .into_par_iter()
+        .map(|i| {
+            let h = cells[i];
+            neighbours[i]
+                .iter()
+                .fold(h, |acc, &n| acc + flow(cells[n], h) - flow(h, cells[n]))
+        })
+        .collect();
+    *cells = slumped;
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::hex_map::HexMapShape;
+    use super::super::TiledGameWorld;
+    use super::*;
+    use crate::geometry::{Hex, OffsetCoord, OffsetKind, WrapMode};
+
+    fn slope(width: usize, height: usize) -> (HexMap<f64>, WrappingGrid) {
+        let shape = HexMapShape::Rectangle {
+            width: width,
+            height: height,
+        };
+        let grid = WrappingGrid::new(OffsetKind::OddR, width, height, WrapMode::Cylinder);
+        // a ramp down to the sea in the south, with a little roughness for water to follow
+        let heights = HexMap::from_fn(shape, |hex| {
+            let offset = OffsetCoord::from_hex(&hex, OffsetKind::OddR);
+            let bump = ((offset.col * 7 + offset.row * 13) % 5) as f64 * 0.001;
+            1.0 - offset.row as f64 * 0.02 + bump
+        });
+        (heights, grid)
+    }
+
+    fn total(heights: &HexMap<f64>) -> f64 {
+        heights.values().sum()
+    }
+
+    #[test]
+    fn erosion_is_deterministic_and_keeps_material() {
+        let (original, grid) = slope(24, 20);
+        let config = ErosionConfig {
+            iterations: 3,
+            ..ErosionConfig::default()
+        };
+        let eroded = |seed: &ProcSeed| {
+            let mut heights = original.clone();
+            // no sea, so that nothing is washed away
+            erode(&mut heights, &grid, seed, &config, 0.0);
+            heights
+        };
+        let seed = ProcSeed::new(&"erosion", 0.0);
+        let a = eroded(&seed);
+        assert!(a == eroded(&seed));
+        assert!(a != original);
+        assert!(a != eroded(&ProcSeed::new(&"other", 0.0)));
+        assert!((total(&a) - total(&original)).abs() < 1e-9);
+
+        let mut unchanged = original.clone();
+        erode(
+            &mut unchanged,
+            &grid,
+            &seed,
+            &ErosionConfig {
+                iterations: 0,
+                ..config.clone()
+            },
+            0.0,
+        );
+        assert!(unchanged == original);
+        assert_eq!(
+            ErosionConfig::from_ron(&config.to_ron().unwrap()).unwrap(),
+            config
+        );
+
+        // a tile without a height is never the way down, and does not stop the run or spread
+        let mut broken = original.clone();
+        let (hole, height) = broken.iter_mut().next().unwrap();
+        *height = f64::NAN;
+        erode(&mut broken, &grid, &seed, &config, 0.0);
+        assert!(broken[hole].is_nan());
+        assert!(broken.iter().all(|(hex, h)| hex == hole || h.is_finite()));
+        assert!(broken
+            .iter()
+            .zip(original.values())
+            .any(|((hex, after), before)| hex != hole && after != before));
+    }
+
+    #[test]
+    fn droplets_carve_valleys_downhill() {
+        let (original, grid) = slope(24, 20);
+        let mut heights = original.clone();
+        let seed = ProcSeed::new(&"erosion", 0.0);
+        erode(&mut heights, &grid, &seed, &ErosionConfig::default(), 0.7);
+
+        // the hills lose material, which ends up further down
+        let upper = |map: &HexMap<f64>| {
+            map.iter()
+                .filter(|(hex, _)| OffsetCoord::from_hex(hex, OffsetKind::OddR).row < 10)
+                .map(|(_, h)| *h)
+                .sum::<f64>()
+        };
+        assert!(upper(&heights) < upper(&original) - 0.05);
+        // without silting the sea up into new land
+        for ((_, before), after) in original.iter().zip(heights.values()) {
+            assert!(*before >= 0.7 || *after < 0.7);
+        }
+
+        // and each row of the ramp is now cut into valleys and the ridges between them
+        let row_spread = |map: &HexMap<f64>, row: isize| {
+            let row: Vec<f64> = (0..24)
+                .map(|col| map[OffsetCoord::new(col, row).to_hex(OffsetKind::OddR)])
+                .collect();
+            let low = row.iter().cloned().fold(f64::INFINITY, f64::min);
+            let high = row.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            high - low
+        };
+        assert!(row_spread(&heights, 6) > 2.0 * row_spread(&original, 6));
+    }
+
+    #[test]
+    fn thermal_passes_flatten_steep_slopes() {
+        let shape = HexMapShape::Hexagon {
+            centre: Hex::new(0, 0, 0),
+            radius: 6,
+        };
+        let grid = WrappingGrid::new(OffsetKind::OddR, 100, 100, WrapMode::Bounded);
+        let mut heights = HexMap::new(shape, 0.0);
+        heights[Hex::new(0, 0, 0)] = 1.0;
+        let before = total(&heights);
+        let config = ErosionConfig {
+            iterations: 400,
+            // no droplets, only slumping
+            droplet_lifetime: 0,
+            ..ErosionConfig::default()
+        };
+        erode(
+            &mut heights,
+            &grid,
+            &ProcSeed::new(&0u32, 0.0),
+            &config,
+            0.0,
+        );
+
+        assert!((total(&heights) - before).abs() < 1e-9);
+        for (hex, h) in heights.iter() {
+            for (_, n) in heights.neighbours(&hex) {
+                assert!(h - n < config.talus * 1.5, "{} next to {}", h, n);
+            }
+        }
+    }
+
+    #[test]
+    fn worlds_sample_their_eroded_heights() {
+        let mut world = TiledGameWorld::new(20, 16);
+        world.set_wrap(WrapMode::Cylinder);
+        let seed = ProcSeed::new(&0u32, 0.0);
+        let raw = world.sample_tiles(&seed);
+        world.erode(
+            &seed,
+            &ErosionConfig {
+                iterations: 2,
+                ..ErosionConfig::default()
+            },
+        );
+        let heights = world.get_heightmap(&seed).unwrap().clone();
+        let tiles = world.sample_tiles(&seed);
+        assert!(tiles.map(|_, s| s.height) == heights);
+        assert!(tiles != raw);
+
+        let hex = OffsetCoord::new(3, 5).to_hex(OffsetKind::OddR);
+        assert_eq!(world.get_tile_at(3.5, 5.0), hex);
+        assert_eq!(world.get_tile_at(23.4, 5.2), hex);
+        assert_eq!(world.get_sample_at(&seed, 3.5, 5.0), tiles[hex]);
+        let region = world.sample_region(&seed);
+        assert_eq!(region.get(5 * 20 + 3).height, heights[hex]);
+
+        let other = ProcSeed::new(&1u32, 0.0);
+        assert!(world.get_heightmap(&other).is_none());
+        assert!(world.sample_tiles(&other) == TiledGameWorld::new(20, 16).sample_tiles(&other));
+        world.set_wrap(WrapMode::Bounded);
+        assert!(world.get_heightmap(&seed).is_none());
+    }
+}
@@ -0,0 +1,274 @@
+use crate::geometry::{
+    Coord, Hex, HexManhattanIterator, HexRectangleIterator, OffsetCoord, OffsetKind,
+};
+use crate::procgen::noise::to_axes;
+use crate::procgen::procseed::ProcSeed;
+use crate::procgen::terrain::{Terrain, TerrainSample};
+
+use rayon::prelude::*;
+use std::cmp::{max, min};
+use std::ops::{Index, IndexMut};
+
+/// Hexes sampled together by `HexMap::from_terrain`.
+const TERRAIN_BATCH: usize = 256;
+
+/// The set of hexes a `HexMap` stores a value for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HexMapShape {
+    /// Every hex within `radius` steps of `centre`.
+    Hexagon { centre: Hex, radius: isize },
+    /// `width` hexes by `height` rows of an `OffsetKind::OddR` grid starting at the origin, so
+    /// that the map covers a rectangle on screen.
+    Rectangle { width: usize, height: usize },
+}
+
+impl HexMapShape {
+    pub fn len(&self) -> usize {
+        match *self {
+            HexMapShape::Hexagon { radius, .. } => (3 * radius * (radius + 1) + 1) as usize,
+            HexMapShape::Rectangle { width, height } => width * height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Position of `hex` in row-major storage order, or `None` if the shape does not contain it.
+    pub fn index(&self, hex: &Hex) -> Option<usize> {
+        match *self {
+            HexMapShape::Hexagon { centre, radius } => {
+                let v = hex - centre;
+                if v.manhattan() > radius {
+                    return None;
+                }
+                // rows above the centre grow by one hex each, rows below shrink by one
+                let rows_before = v.y + radius;
+                let above = min(rows_before, radius);
+                let below = rows_before - above;
+                let before =
+                    above * (radius + 1) + above * (above - 1) / 2 + below * (2 * radius + 1)
+                        - below * (below - 1) / 2;
+                Some((before + v.x - max(-radius, -radius - v.y)) as usize)
+            }
+            HexMapShape::Rectangle { width, height } => {
+                let OffsetCoord { col, row } = OffsetCoord::from_hex(hex, OffsetKind::OddR);
+                if row < 0 || row >= height as isize || col < 0 || col >= width as isize {
+                    return None;
+                }
+                Some(row as usize * width + col as usize)
+            }
+        }
+    }
+
+    pub fn contains(&self, hex: &Hex) -> bool {
+        self.index(hex).is_some()
+    }
+
+    /// The hexes of the shape in storage order.
+    pub fn hexes(&self) -> Box<dyn Iterator<Item = Hex>> {
+        match *self {
+            HexMapShape::Hexagon { centre, radius } => {
+                // HexManhattanIterator walks columns, so transpose it into rows
+                let mut hexes: Vec<Hex> = HexManhattanIterator::new(radius)
+                    .map(|h| h + centre)
+                    .collect();
+                hexes.sort_by_key(|h| (h.y, h.x));
+                Box::new(hexes.into_iter())
+            }
+            HexMapShape::Rectangle { width, height } => {
+                Box::new(HexRectangleIterator::new(OffsetKind::OddR, width, height))
+            }
+        }
+    }
+}
+
+/// Dense storage of one value per hex of a fixed shape.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HexMap<T> {
+    shape: HexMapShape,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> HexMap<T> {
+    pub fn new(shape: HexMapShape, value: T) -> HexMap<T> {
+        HexMap {
+            shape: shape,
+            cells: vec![value; shape.len()],
+        }
+    }
+}
+
+impl<T> HexMap<T> {
+    pub fn from_fn<F: FnMut(Hex) -> T>(shape: HexMapShape, f: F) -> HexMap<T> {
+        HexMap {
+            shape: shape,
+            cells: shape.hexes().map(f).collect(),
+        }
+    }
+
+    pub fn get_shape(&self) -> HexMapShape {
+        self.shape
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn contains(&self, hex: &Hex) -> bool {
+        self.shape.contains(hex)
+    }
+
+    pub fn get(&self, hex: &Hex) -> Option<&T> {
+        self.shape.index(hex).map(move |i| &self.cells[i])
+    }
+
+    pub fn get_mut(&mut self, hex: &Hex) -> Option<&mut T> {
+        match self.shape.index(hex) {
+            Some(i) => Some(&mut self.cells[i]),
+            None => None,
+        }
+    }
+
+    /// Replaces the value at `hex`, returning the old one, or `None` if the map does not
+    /// contain `hex`.
+    pub fn set(&mut self, hex: &Hex, value: T) -> Option<T> {
+        self.get_mut(hex).map(|cell| std::mem::replace(cell, value))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Hex, &T)> {
+        self.shape.hexes().zip(self.cells.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Hex, &mut T)> {
+        self.shape.hexes().zip(self.cells.iter_mut())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.cells.iter()
+    }
+
+    /// The neighbours of `hex` that lie inside the map, whether or not `hex` itself does.
+    pub fn neighbours<'a>(&'a self, hex: &Hex) -> impl Iterator<Item = (Hex, &'a T)> + 'a {
+        let neighbours = hex.neighbours();
+        (0..6).filter_map(move |i| self.get(&neighbours[i]).map(|v| (neighbours[i], v)))
+    }
+
+    pub fn map<U, F: FnMut(Hex, &T) -> U>(&self, mut f: F) -> HexMap<U> {
+        HexMap {
+            shape: self.shape,
+            cells: self.iter().map(|(h, v)| f(h, v)).collect(),
+        }
+    }
+}
+
+impl HexMap<TerrainSample> {
+    /// Samples `terrain` at every hex of `shape` in parallel batches, with `to_point` placing
+    /// hexes in terrain space.
+    pub fn from_terrain<R: Terrain + Sync, F: Fn(&Hex) -> Vec<f64> + Sync>(
+        shape: HexMapShape,
+        terrain: &R,
+        seed: &ProcSeed,
+        to_point: F,
+    ) -> HexMap<TerrainSample> {
+        let hexes: Vec<Hex> = shape.hexes().collect();
+        HexMap {
+            shape: shape,
+            cells: hexes
+                .par_chunks(TERRAIN_BATCH)
+                .flat_map_iter(|chunk| {
+                    let points: Vec<Vec<f64>> = chunk.iter().map(&to_point).collect();
+                    terrain.get_sample_batch(seed, &to_axes(&points))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<T> Index<Hex> for HexMap<T> {
+    type Output = T;
+
+    fn index(&self, hex: Hex) -> &T {
+        self.get(&hex)
+            .unwrap_or_else(|| panic!("hex ({}, {}, {}) is outside the map", hex.x, hex.y, hex.z))
+    }
+}
+
+impl<T> IndexMut<Hex> for HexMap<T> {
+    fn index_mut(&mut self, hex: Hex) -> &mut T {
+        match self.shape.index(&hex) {
+            Some(i) => &mut self.cells[i],
+            None => panic!("hex ({}, {}, {}) is outside the map", hex.x, hex.y, hex.z),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check_dense(shape: HexMapShape) {
+        let hexes: Vec<Hex> = shape.hexes().collect();
+        assert_eq!(hexes.len(), shape.len());
+        for (i, h) in hexes.iter().enumerate() {
+            assert_eq!(shape.index(h), Some(i));
+        }
+    }
+
+    #[test]
+    fn dense_indexing() {
+        for radius in 0..6 {
+            check_dense(HexMapShape::Hexagon {
+                centre: Hex::new(2, -7, 5),
+                radius: radius,
+            });
+        }
+        check_dense(HexMapShape::Rectangle {
+            width: 7,
+            height: 5,
+        });
+        let shape = HexMapShape::Hexagon {
+            centre: Hex::new(0, 0, 0),
+            radius: 2,
+        };
+        assert_eq!(shape.index(&Hex::new(3, 0, -3)), None);
+        let shape = HexMapShape::Rectangle {
+            width: 3,
+            height: 3,
+        };
+        assert_eq!(shape.index(&Hex::new(0, -1, 1)), None);
+        assert_eq!(shape.index(&Hex::new(-1, 2, -1)), Some(6));
+        assert_eq!(shape.index(&Hex::new(-2, 2, 0)), None);
+    }
+
+    #[test]
+    fn get_set_and_neighbours() {
+        let mut map = HexMap::new(
+            HexMapShape::Rectangle {
+                width: 4,
+                height: 3,
+            },
+            0,
+        );
+        let corner = Hex::new(0, 0, 0);
+        assert_eq!(map.set(&corner, 7), Some(0));
+        assert_eq!(map.set(&Hex::new(-1, 0, 1), 7), None);
+        assert_eq!(map[corner], 7);
+        map[Hex::new(1, 0, -1)] += 2;
+        assert_eq!(map.neighbours(&corner).count(), 2);
+        assert_eq!(map.neighbours(&corner).map(|(_, v)| *v).sum::<i32>(), 2);
+        let doubled = map.map(|_, v| v * 2);
+        assert_eq!(doubled[corner], 14);
+        assert_eq!(doubled.values().sum::<i32>(), 18);
+        assert!(!map.is_empty());
+        assert!(HexMapShape::Rectangle {
+            width: 0,
+            height: 3
+        }
+        .is_empty());
+    }
+}
@@ -1,5 +1,13 @@
+pub mod cache;
+pub mod erosion;
+pub mod hex_map;
+
+use crate::geometry::{Hex, OffsetCoord, OffsetKind, WrapMode, WrappingGrid};
 use crate::procgen::procseed::ProcSeed;
-use crate::procgen::terrain::{Terrain, TiledWorldTerrain};
+use crate::procgen::terrain::{Terrain, TerrainRegion, TerrainSample, TiledWorldTerrain};
+use cache::ChunkCache;
+use erosion::ErosionConfig;
+use hex_map::{HexMap, HexMapShape};
 
 //use cursive::theme::Color;
 use quicksilver::graphics::Color;
@@ -25,14 +33,229 @@ pub struct TiledGameWorld {
     width: usize,
     height: usize,
     terrain: TiledWorldTerrain,
+    wrap: WrapMode,
+    /// Tile heights left by `erode`, and the seed they were generated with.
+    heightmap: Option<(ProcSeed, HexMap<f64>)>,
+    generation: u64,
+}
+
+/// Adapts movement costs or sight queries to a world whose edges wrap around.
+pub struct Wrapped<T> {
+    pub grid: WrappingGrid,
+    pub inner: T,
 }
 
 impl TiledGameWorld {
     pub fn new(width: usize, height: usize) -> TiledGameWorld {
+        TiledGameWorld::with_terrain(width, height, TiledWorldTerrain::new())
+    }
+
+    pub fn with_terrain(width: usize, height: usize, terrain: TiledWorldTerrain) -> TiledGameWorld {
         TiledGameWorld {
             width: width,
             height: height,
-            terrain: TiledWorldTerrain::new(),
+            terrain: terrain,
+            wrap: WrapMode::Bounded,
+            heightmap: None,
+            generation: 0,
+        }
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_terrain(&self) -> &TiledWorldTerrain {
+        &self.terrain
+    }
+
+    pub fn get_wrap(&self) -> WrapMode {
+        self.wrap
+    }
+
+    /// Counts the changes to the world that change its samples for a seed, such as wrapping or
+    /// eroding it, so that caches of samples can tell when they are stale.
+    pub fn get_generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Wrapping worlds have their terrain tiled to match, so that no seam shows where the
+    /// edges meet, and bounded worlds get back the tiling their terrain was configured with. A
+    /// torus needs an even height.
+    pub fn set_wrap(&mut self, wrap: WrapMode) {
+        assert!(
+            WrappingGrid::can_wrap(OffsetKind::OddR, self.width, self.height, wrap),
+            "a {} by {} world cannot wrap as a {:?}",
+            self.width,
+            self.height,
+            wrap
+        );
+        self.terrain.set_tile_distance(match wrap {
+            WrapMode::Bounded => None,
+            // terrain points run from 0 to 1 across the world
+            WrapMode::Cylinder | WrapMode::Torus => Some(1.0),
+        });
+        self.wrap = wrap;
+        self.heightmap = None;
+        self.generation += 1;
+    }
+
+    /// The world's tiles as a grid, for wrap-aware distances and neighbours.
+    pub fn get_grid(&self) -> WrappingGrid {
+        WrappingGrid::new(OffsetKind::OddR, self.width, self.height, self.wrap)
+    }
+
+    /// Converts a position in world units to the unit-square point the terrain is sampled at.
+    pub fn get_terrain_point(&self, x: f64, y: f64) -> Vec<f64> {
+        vec![x / self.width as f64, y / self.height as f64]
+    }
+
+    pub fn get_offset_point(&self, offset: &GameWorldOffset) -> Vec<f64> {
+        self.get_terrain_point(offset.x as f64, offset.y as f64)
+    }
+
+    /// The world's own tiling has one hex per world unit, so that `get_tile_shape` covers the
+    /// world exactly.
+    pub fn get_hex_point(&self, hex: &Hex) -> Vec<f64> {
+        self.get_terrain_point(hex.x as f64 + hex.y as f64 * 0.5, hex.y as f64)
+    }
+
+    pub fn get_tile_shape(&self) -> HexMapShape {
+        HexMapShape::Rectangle {
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Samples the terrain in parallel at every world unit, row by row. Eroded worlds take each
+    /// cell's height from the nearest tile.
+    pub fn sample_region(&self, seed: &ProcSeed) -> TerrainRegion {
+        let mut region = self
+            .terrain
+            .sample_region(seed, self.width, self.height, |x, y| {
+                self.get_terrain_point(x as f64, y as f64)
+            });
+        if self.get_heightmap(seed).is_none() {
+            return region;
+        }
+        for i in 0..region.len() {
+            let (x, y) = ((i % self.width) as f64, (i / self.width) as f64);
+            let sample = self.with_eroded_height(seed, &self.get_tile_at(x, y), region.get(i));
+            region.set(i, sample);
+        }
+        region
+    }
+
+    /// Samples the terrain in parallel at every tile of the world.
+    pub fn sample_tiles(&self, seed: &ProcSeed) -> HexMap<TerrainSample> {
+        let samples = HexMap::from_terrain(self.get_tile_shape(), &self.terrain, seed, |hex| {
+            self.get_hex_point(hex)
+        });
+        match self.get_heightmap(seed) {
+            Some(_) => samples.map(|hex, sample| self.with_eroded_height(seed, &hex, *sample)),
+            None => samples,
+        }
+    }
+
+    /// The terrain sample at `hex`, wrapped into the world, generated on first access through
+    /// `cache`, which is cleared first if the world has changed since it was filled.
+    pub fn get_cached_sample(
+        &self,
+        cache: &mut ChunkCache<TerrainSample>,
+        seed: &ProcSeed,
+        hex: &Hex,
+    ) -> TerrainSample {
+        let hex = self.get_grid().wrap(hex);
+        cache.set_generation(self.generation);
+        *cache.get(seed, &hex, |h| {
+            let sample = self.terrain.get_sample(seed, &self.get_hex_point(h));
+            self.with_eroded_height(seed, h, sample)
+        })
+    }
+
+    /// The terrain sample at a position in world units, with the height of the nearest tile if
+    /// the world has been eroded.
+    pub fn get_sample_at(&self, seed: &ProcSeed, x: f64, y: f64) -> TerrainSample {
+        let sample = self.terrain.get_sample(seed, &self.get_terrain_point(x, y));
+        self.with_eroded_height(seed, &self.get_tile_at(x, y), sample)
+    }
+
+    /// The tile whose centre is nearest to a position in world units, wrapped into the world or
+    /// clamped to its edges.
+    pub fn get_tile_at(&self, x: f64, y: f64) -> Hex {
+        let fit = |v: f64, len: usize, wraps: bool| {
+            let v = v.round() as isize;
+            if wraps {
+                v.rem_euclid(len as isize)
+            } else {
+                v.max(0).min(len as isize - 1)
+            }
+        };
+        let row = fit(y, self.height, self.wrap == WrapMode::Torus);
+        // odd rows are shoved half a tile right
+        let col = fit(
+            x - (row & 1) as f64 * 0.5,
+            self.width,
+            self.wrap != WrapMode::Bounded,
+        );
+        OffsetCoord::new(col, row).to_hex(OffsetKind::OddR)
+    }
+
+    /// Erodes the world's tile heights for `seed` with `erosion::erode` and keeps them, so that
+    /// the world's samples for `seed` use them in place of the raw terrain height. Eroding again
+    /// starts over from the raw heights.
+    pub fn erode(&mut self, seed: &ProcSeed, config: &ErosionConfig) {
+        self.heightmap = None;
+        let mut heights = self.sample_tiles(seed).map(|_, sample| sample.height);
+        erosion::erode(
+            &mut heights,
+            &self.get_grid(),
+            seed,
+            config,
+            self.terrain.get_biome_table().water_level,
+        );
+        self.heightmap = Some((seed.clone(), heights));
+        self.generation += 1;
+    }
+
+    /// The eroded tile heights, if the world has been eroded with `seed`.
+    pub fn get_heightmap(&self, seed: &ProcSeed) -> Option<&HexMap<f64>> {
+        match &self.heightmap {
+            Some((eroded, heights)) if eroded == seed => Some(heights),
+            _ => None,
+        }
+    }
+
+    pub fn clear_heightmap(&mut self) {
+        self.heightmap = None;
+        self.generation += 1;
+    }
+
+    /// `sample` with the eroded height of `hex` and the biome that height gives, if there is one.
+    fn with_eroded_height(
+        &self,
+        seed: &ProcSeed,
+        hex: &Hex,
+        sample: TerrainSample,
+    ) -> TerrainSample {
+        match self
+            .get_heightmap(seed)
+            .and_then(|heights| heights.get(hex))
+        {
+            Some(&height) => TerrainSample {
+                height: height,
+                biome: self.terrain.get_biome_table().classify(
+                    height,
+                    sample.temperature,
+                    sample.precipitation,
+                ),
+                ..sample
+            },
+            None => sample,
         }
     }
 }
@@ -43,10 +266,60 @@ impl GameWorld for TiledGameWorld {
     }
 
     fn render_qs(&self, seed: &ProcSeed, offset: &GameWorldOffset) -> (Color, Color) {
-        let tv = vec![
-            offset.x as f64 / self.width as f64,
-            offset.y as f64 / self.height as f64,
-        ];
+        let tv = self.get_offset_point(offset);
         self.terrain.render_qs(seed, &tv)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::procgen::terrain::config::{NoiseLayerConfig, TerrainConfig};
+
+    #[test]
+    fn bounding_a_wrapped_world_restores_its_tiling() {
+        let default = TerrainConfig::default();
+        let config = TerrainConfig {
+            heightmap: NoiseLayerConfig {
+                tile_distance: 2.0,
+                ..default.heightmap
+            },
+            ..default
+        };
+        let mut world = TiledGameWorld::with_terrain(8, 6, TiledWorldTerrain::from_config(&config));
+        let seed = ProcSeed::new(&3u32, 0.0);
+        let point = vec![0.3, 0.7];
+        let configured = world.get_terrain().get_height(&seed, &point);
+
+        world.set_wrap(WrapMode::Cylinder);
+        assert!(world.get_terrain().get_height(&seed, &point) != configured);
+        world.set_wrap(WrapMode::Bounded);
+        assert_eq!(world.get_terrain().get_height(&seed, &point), configured);
+    }
+
+    #[test]
+    fn eroding_refreshes_cached_samples() {
+        let mut world = TiledGameWorld::new(12, 10);
+        let seed = ProcSeed::new(&3u32, 0.0);
+        let mut cache = ChunkCache::new(4, 1 << 20);
+        let hexes: Vec<Hex> = world.get_tile_shape().hexes().collect();
+        let raw: Vec<f64> = hexes
+            .iter()
+            .map(|hex| world.get_cached_sample(&mut cache, &seed, hex).height)
+            .collect();
+
+        world.erode(&seed, &ErosionConfig::default());
+        let heights = world.get_heightmap(&seed).unwrap();
+        for hex in hexes.iter() {
+            let sample = world.get_cached_sample(&mut cache, &seed, hex);
+            assert_eq!(sample.height, heights[*hex]);
+        }
+        assert!(hexes.iter().zip(raw).any(|(hex, raw)| heights[*hex] != raw));
+    }
+
+    #[test]
+    #[should_panic]
+    fn odd_torus_worlds_are_refused() {
+        TiledGameWorld::new(8, 5).set_wrap(WrapMode::Torus);
+    }
+}
@@ -7,15 +7,287 @@ use std::cmp::{max, min, Ordering};
 
 /// We use cube coordinates as described at https://www.redblobgames.com/grids/hexagons/
 /// and https://www.redblobgames.com/grids/hexagons/implementation.html
-trait Coord {
+pub trait Coord {
     type Scalar;
     fn cmp_len(&self, length: Self::Scalar) -> Ordering;
     fn manhattan(&self) -> Self::Scalar;
     fn manhattan_iter(length: Self::Scalar) -> Box<Iterator<Item = Self>>;
     fn to_cartesian(&self, size: Vector) -> Vector;
+
+    /// Number of steps between two coordinates.
+    fn distance(&self, other: &Self) -> Self::Scalar;
+    /// The adjacent coordinate in one of the six directions, counted anticlockwise from east.
+    fn neighbour(&self, direction: usize) -> Self;
+    fn neighbours(&self) -> [Self; 6]
+    where
+        Self: Sized;
+    /// The coordinate two steps away between two neighbours, counted anticlockwise from
+    /// east-north-east.
+    fn diagonal(&self, direction: usize) -> Self;
+    fn diagonals(&self) -> [Self; 6]
+    where
+        Self: Sized;
+    /// The coordinates on the straight line to `other`, inclusive of both ends.
+    fn line_to(&self, other: &Self) -> Vec<Self>
+    where
+        Self: Sized;
+    /// Rotates about `centre` by 60 degrees per step, anticlockwise for positive steps.
+    fn rotate(&self, centre: &Self, steps: isize) -> Self;
+    /// Reflects about the axis through `centre` along which the x coordinate is constant.
+    fn reflect_x(&self, centre: &Self) -> Self;
+    /// Reflects about the axis through `centre` along which the y coordinate is constant.
+    fn reflect_y(&self, centre: &Self) -> Self;
+    /// Reflects about the axis through `centre` along which the z coordinate is constant.
+    fn reflect_z(&self, centre: &Self) -> Self;
+}
+
+pub type Hex = Vector3<isize>;
+
+/// A point between hex centres in cube coordinates, as produced by interpolation.
+pub type FractionalHex = Vector3<f64>;
+
+const HEX_DIRECTIONS: [(isize, isize, isize); 6] = [
+    (1, 0, -1),
+    (1, -1, 0),
+    (0, -1, 1),
+    (-1, 0, 1),
+    (-1, 1, 0),
+    (0, 1, -1),
+];
+
+const HEX_DIAGONALS: [(isize, isize, isize); 6] = [
+    (2, -1, -1),
+    (1, -2, 1),
+    (-1, -1, 2),
+    (-2, 1, 1),
+    (-1, 2, -1),
+    (1, 1, -2),
+];
+
+pub fn hex_direction(direction: usize) -> Hex {
+    let (x, y, z) = HEX_DIRECTIONS[direction % 6];
+    Hex::new(x, y, z)
+}
+
+pub fn hex_diagonal(direction: usize) -> Hex {
+    let (x, y, z) = HEX_DIAGONALS[direction % 6];
+    Hex::new(x, y, z)
 }
 
-type Hex = Vector3<isize>;
+pub fn hex_lerp(a: &FractionalHex, b: &FractionalHex, t: f64) -> FractionalHex {
+    a + (b - a) * t
+}
+
+/// Rounds to the hex containing a fractional position, by rounding each coordinate and then
+/// fixing up the one that moved furthest so that the coordinates still sum to zero.
+pub fn hex_round(h: &FractionalHex) -> Hex {
+    let mut x = h.x.round();
+    let mut y = h.y.round();
+    let mut z = h.z.round();
+    let dx = (x - h.x).abs();
+    let dy = (y - h.y).abs();
+    let dz = (z - h.z).abs();
+    if dx > dy && dx > dz {
+        x = -y - z;
+    } else if dy > dz {
+        y = -x - z;
+    } else {
+        z = -x - y;
+    }
+    Hex::new(x as isize, y as isize, z as isize)
+}
+
+fn to_fractional(h: &Hex) -> FractionalHex {
+    FractionalHex::new(h.x as f64, h.y as f64, h.z as f64)
+}
+
+/// The inverse of `Coord::to_cartesian`: the fractional hex position of a point relative to the
+/// centre of the origin hex.
+pub fn cartesian_to_hex(point: Vector, size: Vector) -> FractionalHex {
+    Layout::new(Orientation::POINTY, size, Vector::ZERO).to_hex(point)
+}
+
+/// Which way up hexes are drawn, as the mapping from cube coordinates to the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Orientation {
+    /// Row-major matrix taking cube x and y to screen x and y, in units of the hex size.
+    forward: [f64; 4],
+    /// Corner offsets from the centre in units of the hex size, clockwise on screen.
+    corners: [(f32, f32); 6],
+}
+
+impl Orientation {
+    /// Hexes with a corner at the top, laid out in rows. Cube y counts rows downwards.
+    pub const POINTY: Orientation = Orientation {
+        forward: [1.0, 0.5, 0.0, 0.75],
+        corners: [
+            (0.0, -0.5),
+            (0.5, -0.25),
+            (0.5, 0.25),
+            (0.0, 0.5),
+            (-0.5, 0.25),
+            (-0.5, -0.25),
+        ],
+    };
+
+    /// Hexes with an edge at the top, laid out in columns. Cube x counts columns rightwards.
+    pub const FLAT: Orientation = Orientation {
+        forward: [0.75, 0.0, 0.5, 1.0],
+        corners: [
+            (-0.5, 0.0),
+            (-0.25, -0.5),
+            (0.25, -0.5),
+            (0.5, 0.0),
+            (0.25, 0.5),
+            (-0.25, 0.5),
+        ],
+    };
+
+    /// The size of a regular hex with the given distance from centre to corner.
+    pub fn size_with_radius(&self, radius: f32) -> Vector {
+        if *self == Orientation::FLAT {
+            Vector::new(radius * 2.0, radius * 3.0f32.sqrt())
+        } else {
+            Vector::new(radius * 3.0f32.sqrt(), radius * 2.0)
+        }
+    }
+}
+
+/// Places hexes on the screen: `size` is the width and height of one hex's bounding box and
+/// `origin` is where the centre of the origin hex is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub orientation: Orientation,
+    pub size: Vector,
+    pub origin: Vector,
+}
+
+impl Layout {
+    pub fn new(orientation: Orientation, size: Vector, origin: Vector) -> Layout {
+        Layout {
+            orientation,
+            size,
+            origin,
+        }
+    }
+
+    pub fn with_radius(orientation: Orientation, radius: f32, origin: Vector) -> Layout {
+        Layout::new(orientation, orientation.size_with_radius(radius), origin)
+    }
+
+    /// The centre of `hex` on the screen.
+    pub fn to_screen(&self, hex: &Hex) -> Vector {
+        let f = &self.orientation.forward;
+        let (x, y) = (hex.x as f64, hex.y as f64);
+        Vector::new(
+            (f[0] * x + f[1] * y) as f32 * self.size.x,
+            (f[2] * x + f[3] * y) as f32 * self.size.y,
+        ) + self.origin
+    }
+
+    /// The fractional hex position of a point on the screen.
+    pub fn to_hex(&self, point: Vector) -> FractionalHex {
+        let f = &self.orientation.forward;
+        let px = (point.x - self.origin.x) as f64 / self.size.x as f64;
+        let py = (point.y - self.origin.y) as f64 / self.size.y as f64;
+        let det = f[0] * f[3] - f[1] * f[2];
+        let x = (f[3] * px - f[1] * py) / det;
+        let y = (f[0] * py - f[2] * px) / det;
+        FractionalHex::new(x, y, -x - y)
+    }
+
+    /// The hex drawn over a point on the screen.
+    pub fn pick(&self, point: Vector) -> Hex {
+        hex_round(&self.to_hex(point))
+    }
+
+    /// The corners of `hex` on the screen, clockwise.
+    pub fn corners(&self, hex: &Hex) -> [Vector; 6] {
+        let centre = self.to_screen(hex);
+        let mut corners = [centre; 6];
+        for (corner, (x, y)) in corners.iter_mut().zip(self.orientation.corners.iter()) {
+            *corner = centre + Vector::new(x * self.size.x, y * self.size.y);
+        }
+        corners
+    }
+
+    pub fn shape(&self, hex: &Hex) -> HexShape {
+        HexShape {
+            pos: self.to_screen(hex),
+            size: self.size,
+            orientation: self.orientation,
+        }
+    }
+}
+
+/// Axial coordinates: the cube x and y, with z left implied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Axial {
+    pub q: isize,
+    pub r: isize,
+}
+
+impl Axial {
+    pub fn new(q: isize, r: isize) -> Axial {
+        Axial { q, r }
+    }
+
+    pub fn from_hex(hex: &Hex) -> Axial {
+        Axial::new(hex.x, hex.y)
+    }
+
+    pub fn to_hex(&self) -> Hex {
+        Hex::new(self.q, self.r, -self.q - self.r)
+    }
+}
+
+/// Which rows or columns of an offset grid are shoved half a hex along. Row offsets suit
+/// pointy-top layouts and column offsets suit flat-top ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OffsetKind {
+    /// Odd rows are shoved right.
+    OddR,
+    /// Even rows are shoved right.
+    EvenR,
+    /// Odd columns are shoved down.
+    OddQ,
+    /// Even columns are shoved down.
+    EvenQ,
+}
+
+/// Column and row in a rectangular grid of hexes, for storing and displaying rectangular maps.
+/// Cube y counts rows and cube x counts columns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OffsetCoord {
+    pub col: isize,
+    pub row: isize,
+}
+
+impl OffsetCoord {
+    pub fn new(col: isize, row: isize) -> OffsetCoord {
+        OffsetCoord { col, row }
+    }
+
+    pub fn from_hex(hex: &Hex, kind: OffsetKind) -> OffsetCoord {
+        match kind {
+            OffsetKind::OddR => OffsetCoord::new(hex.x + (hex.y - (hex.y & 1)) / 2, hex.y),
+            OffsetKind::EvenR => OffsetCoord::new(hex.x + (hex.y + (hex.y & 1)) / 2, hex.y),
+            OffsetKind::OddQ => OffsetCoord::new(hex.x, hex.y + (hex.x - (hex.x & 1)) / 2),
+            OffsetKind::EvenQ => OffsetCoord::new(hex.x, hex.y + (hex.x + (hex.x & 1)) / 2),
+        }
+    }
+
+    pub fn to_hex(&self, kind: OffsetKind) -> Hex {
+        let (col, row) = (self.col, self.row);
+        let (x, y) = match kind {
+            OffsetKind::OddR => (col - (row - (row & 1)) / 2, row),
+            OffsetKind::EvenR => (col - (row + (row & 1)) / 2, row),
+            OffsetKind::OddQ => (col, row - (col - (col & 1)) / 2),
+            OffsetKind::EvenQ => (col, row - (col + (col & 1)) / 2),
+        };
+        Hex::new(x, y, -x - y)
+    }
+}
 
 impl Coord for Hex {
     type Scalar = isize;
@@ -40,10 +312,81 @@ impl Coord for Hex {
     }
 
     fn to_cartesian(&self, size: Vector) -> Vector {
-        Vector::new(
-            self.x as f32 * size.x + self.y as f32 * 0.5 * size.x,
-            self.y as f32 * 0.75 * size.y,
-        )
+        Layout::new(Orientation::POINTY, size, Vector::ZERO).to_screen(self)
+    }
+
+    fn distance(&self, other: &Self) -> Self::Scalar {
+        (self - other).manhattan()
+    }
+
+    fn neighbour(&self, direction: usize) -> Self {
+        self + hex_direction(direction)
+    }
+
+    fn neighbours(&self) -> [Self; 6] {
+        [
+            self.neighbour(0),
+            self.neighbour(1),
+            self.neighbour(2),
+            self.neighbour(3),
+            self.neighbour(4),
+            self.neighbour(5),
+        ]
+    }
+
+    fn diagonal(&self, direction: usize) -> Self {
+        self + hex_diagonal(direction)
+    }
+
+    fn diagonals(&self) -> [Self; 6] {
+        [
+            self.diagonal(0),
+            self.diagonal(1),
+            self.diagonal(2),
+            self.diagonal(3),
+            self.diagonal(4),
+            self.diagonal(5),
+        ]
+    }
+
+    fn line_to(&self, other: &Self) -> Vec<Self> {
+        let n = self.distance(other);
+        // nudge off the edges between hexes so that rounding never has to break a tie
+        let nudge = FractionalHex::new(1e-6, 2e-6, -3e-6);
+        let a = to_fractional(self) + nudge;
+        let b = to_fractional(other) + nudge;
+        (0..=n)
+            .map(|i| {
+                if n == 0 {
+                    *self
+                } else {
+                    hex_round(&hex_lerp(&a, &b, i as f64 / n as f64))
+                }
+            })
+            .collect()
+    }
+
+    fn rotate(&self, centre: &Self, steps: isize) -> Self {
+        let mut v = self - centre;
+        for _ in 0..steps.rem_euclid(6) {
+            v = Hex::new(-v.z, -v.x, -v.y);
+        }
+        centre + v
+    }
+
+    fn reflect_x(&self, centre: &Self) -> Self {
+        let v = self - centre;
+        centre + Hex::new(v.x, v.z, v.y)
+    }
+
+    fn reflect_y(&self, centre: &Self) -> Self {
+        let v = self - centre;
+        centre + Hex::new(v.z, v.y, v.x)
+    }
+
+    fn reflect_z(&self, centre: &Self) -> Self {
+        let v = self - centre;
+        centre + Hex::new(v.y, v.x, v.z)
     }
 }
 
@@ -81,21 +424,314 @@ impl Iterator for HexManhattanIterator {
     }
 }
 
-trait HexTiling {
-    type HexCoord;
+/// Walks the hexes at exactly `radius` steps from `centre`, anticlockwise from the south-west
+/// corner.
+pub struct HexRingIterator {
+    hex: Hex,
+    radius: isize,
+    side: usize,
+    step: isize,
+}
 
-    fn new() -> Self;
-    fn origin() -> Self::HexCoord;
+impl HexRingIterator {
+    pub fn new(centre: Hex, radius: isize) -> Self {
+        HexRingIterator {
+            hex: centre + hex_direction(4) * radius,
+            radius,
+            side: 0,
+            step: 0,
+        }
+    }
+}
+
+impl Iterator for HexRingIterator {
+    type Item = Hex;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.radius == 0 {
+            // the ring of radius 0 is just the centre
+            self.radius = -1;
+            return Some(self.hex);
+        }
+        if self.radius < 0 || self.side >= 6 {
+            return None;
+        }
+        let result = self.hex;
+        self.hex = self.hex.neighbour(self.side);
+        self.step += 1;
+        if self.step == self.radius {
+            self.step = 0;
+            self.side += 1;
+        }
+        Some(result)
+    }
+}
+
+/// Walks the hexes within `radius` steps of `centre`, ring by ring outwards.
+pub struct HexSpiralIterator {
+    centre: Hex,
+    radius: isize,
+    ring: HexRingIterator,
+}
+
+impl HexSpiralIterator {
+    pub fn new(centre: Hex, radius: isize) -> Self {
+        HexSpiralIterator {
+            centre,
+            radius,
+            ring: HexRingIterator::new(centre, 0),
+        }
+    }
+}
+
+impl Iterator for HexSpiralIterator {
+    type Item = Hex;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(hex) = self.ring.next() {
+                return Some(hex);
+            }
+            let next = self.ring.radius.max(0) + 1;
+            if next > self.radius {
+                return None;
+            }
+            self.ring = HexRingIterator::new(self.centre, next);
+        }
+    }
+}
+
+/// The hexes whose coordinates each lie within inclusive bounds. Any number of hexagonal
+/// ranges can be intersected this way, and the result is still a range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HexRange {
+    pub min: Hex,
+    pub max: Hex,
+}
+
+impl HexRange {
+    /// All hexes within `radius` steps of `centre`.
+    pub fn new(centre: Hex, radius: isize) -> Self {
+        HexRange {
+            min: centre - Hex::new(radius, radius, radius),
+            max: centre + Hex::new(radius, radius, radius),
+        }
+    }
+
+    pub fn intersection(&self, other: &HexRange) -> HexRange {
+        HexRange {
+            min: Hex::new(
+                max(self.min.x, other.min.x),
+                max(self.min.y, other.min.y),
+                max(self.min.z, other.min.z),
+            ),
+            max: Hex::new(
+                min(self.max.x, other.max.x),
+                min(self.max.y, other.max.y),
+                min(self.max.z, other.max.z),
+            ),
+        }
+    }
+
+    pub fn contains(&self, hex: &Hex) -> bool {
+        (self.min.x..=self.max.x).contains(&hex.x)
+            && (self.min.y..=self.max.y).contains(&hex.y)
+            && (self.min.z..=self.max.z).contains(&hex.z)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Hex> {
+        let range = *self;
+        (range.min.x..=range.max.x).flat_map(move |x| {
+            let y_min = max(range.min.y, -x - range.max.z);
+            let y_max = min(range.max.y, -x - range.min.z);
+            (y_min..=y_max).map(move |y| Hex::new(x, y, -x - y))
+        })
+    }
+}
+
+/// Walks a `width` by `height` offset grid row by row, from column 0 of row 0.
+pub struct HexRectangleIterator {
+    kind: OffsetKind,
+    width: isize,
+    height: isize,
+    col: isize,
+    row: isize,
+}
+
+impl HexRectangleIterator {
+    pub fn new(kind: OffsetKind, width: usize, height: usize) -> Self {
+        HexRectangleIterator {
+            kind,
+            width: width as isize,
+            height: height as isize,
+            col: 0,
+            row: 0,
+        }
+    }
+}
+
+impl Iterator for HexRectangleIterator {
+    type Item = Hex;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.width == 0 || self.row >= self.height {
+            return None;
+        }
+        let result = OffsetCoord::new(self.col, self.row).to_hex(self.kind);
+        self.col += 1;
+        if self.col == self.width {
+            self.col = 0;
+            self.row += 1;
+        }
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = if self.width == 0 || self.row >= self.height {
+            0
+        } else {
+            ((self.height - self.row) * self.width - self.col) as usize
+        };
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for HexRectangleIterator {}
+
+/// Which edges of a rectangular grid join up with the opposite edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WrapMode {
+    /// No edges join up.
+    Bounded,
+    /// The east and west edges join up, as on a globe.
+    Cylinder,
+    /// Both pairs of edges join up.
+    Torus,
+}
+
+/// A `width` by `height` offset grid whose edges may wrap around. Hexes off a wrapping edge are
+/// the same place as the hexes one circumference back, and distances take the shorter way
+/// round.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WrappingGrid {
+    kind: OffsetKind,
+    width: usize,
+    height: usize,
+    mode: WrapMode,
+}
+
+impl WrappingGrid {
+    /// Wrapping the rows or columns that are shoved along only lines up if there is an even
+    /// number of them, so this panics if an odd-sized grid is asked to wrap along that axis.
+    pub fn new(kind: OffsetKind, width: usize, height: usize, mode: WrapMode) -> WrappingGrid {
+        assert!(
+            WrappingGrid::can_wrap(kind, width, height, mode),
+            "a {} by {} grid of {:?} offsets cannot wrap as a {:?}",
+            width,
+            height,
+            kind,
+            mode
+        );
+        WrappingGrid {
+            kind,
+            width,
+            height,
+            mode,
+        }
+    }
+
+    /// Whether `new` accepts a grid of this shape and wrapping.
+    pub fn can_wrap(kind: OffsetKind, width: usize, height: usize, mode: WrapMode) -> bool {
+        let shoves_rows = kind == OffsetKind::OddR || kind == OffsetKind::EvenR;
+        if shoves_rows {
+            mode != WrapMode::Torus || height % 2 == 0
+        } else {
+            mode == WrapMode::Bounded || width % 2 == 0
+        }
+    }
+
+    pub fn get_mode(&self) -> WrapMode {
+        self.mode
+    }
+
+    /// The hex inside the grid that is the same place as `hex`. Hexes off an edge that does not
+    /// wrap are returned unchanged.
+    pub fn wrap(&self, hex: &Hex) -> Hex {
+        let mut offset = OffsetCoord::from_hex(hex, self.kind);
+        if self.mode != WrapMode::Bounded {
+            offset.col = offset.col.rem_euclid(self.width as isize);
+        }
+        if self.mode == WrapMode::Torus {
+            offset.row = offset.row.rem_euclid(self.height as isize);
+        }
+        offset.to_hex(self.kind)
+    }
+
+    /// Whether `hex` is, or wraps around to, a hex of the grid.
+    pub fn contains(&self, hex: &Hex) -> bool {
+        let offset = OffsetCoord::from_hex(&self.wrap(hex), self.kind);
+        (0..self.width as isize).contains(&offset.col)
+            && (0..self.height as isize).contains(&offset.row)
+    }
+
+    /// Number of steps between two hexes the shortest way round.
+    pub fn distance(&self, a: &Hex, b: &Hex) -> isize {
+        let a = self.wrap(a);
+        let b = self.wrap(b);
+        self.translations()
+            .iter()
+            .map(|t| a.distance(&(b + t)))
+            .min()
+            .unwrap()
+    }
+
+    /// The neighbours of `hex`, wrapped into the grid.
+    pub fn neighbours(&self, hex: &Hex) -> [Hex; 6] {
+        let mut neighbours = hex.neighbours();
+        for n in neighbours.iter_mut() {
+            *n = self.wrap(n);
+        }
+        neighbours
+    }
+
+    /// The cube offsets by which a hex can be moved without changing the place it stands for,
+    /// one step either way along each axis. Axes that do not wrap step by zero, so some offsets
+    /// repeat.
+    fn translations(&self) -> [Hex; 9] {
+        let col_step = if self.mode == WrapMode::Bounded {
+            0
+        } else {
+            self.width as isize
+        };
+        let row_step = if self.mode == WrapMode::Torus {
+            self.height as isize
+        } else {
+            0
+        };
+        let zero = OffsetCoord::new(0, 0).to_hex(self.kind);
+        let mut translations = [Hex::new(0, 0, 0); 9];
+        for (i, translation) in translations.iter_mut().enumerate() {
+            let (c, r) = ((i % 3) as isize - 1, (i / 3) as isize - 1);
+            let shifted = OffsetCoord::new(c * col_step, r * row_step);
+            *translation = shifted.to_hex(self.kind) - zero;
+        }
+        translations
+    }
 }
 
 pub struct HexShape {
     pub pos: Vector,
     pub size: Vector,
+    pub orientation: Orientation,
 }
 
 impl HexShape {
     pub fn with_size(pos: Vector, size: Vector) -> HexShape {
-        HexShape { pos, size }
+        HexShape {
+            pos,
+            size,
+            orientation: Orientation::POINTY,
+        }
     }
 
     pub fn with_radius(pos: Vector, radius: f32) -> HexShape {
@@ -103,16 +739,24 @@ impl HexShape {
     }
 
     pub fn with_size_on_grid(grid_pos: Hex, grid_origin: Vector, size: Vector) -> HexShape {
-        HexShape {
-            pos: grid_pos.to_cartesian(size) + grid_origin,
-            size,
-        }
+        Layout::new(Orientation::POINTY, size, grid_origin).shape(&grid_pos)
     }
 
     pub fn with_radius_on_grid(grid_pos: Hex, grid_origin: Vector, radius: f32) -> HexShape {
         let size = Vector::new(radius * 3.0f32.sqrt(), radius * 2.0);
         HexShape::with_size_on_grid(grid_pos, grid_origin, size)
     }
+
+    /// The grid position of the shape `with_size_on_grid` would draw over `point`.
+    pub fn pick_on_grid(point: Vector, grid_origin: Vector, size: Vector) -> Hex {
+        Layout::new(Orientation::POINTY, size, grid_origin).pick(point)
+    }
+
+    /// The grid position of the shape `with_radius_on_grid` would draw over `point`.
+    pub fn pick_on_grid_with_radius(point: Vector, grid_origin: Vector, radius: f32) -> Hex {
+        let size = Vector::new(radius * 3.0f32.sqrt(), radius * 2.0);
+        HexShape::pick_on_grid(point, grid_origin, size)
+    }
 }
 
 impl Drawable for HexShape {
@@ -123,35 +767,11 @@ impl Drawable for HexShape {
         transform: Transform,
         z: impl Scalar,
     ) {
-        // A hexagon rendered as 4 triangles
-        let x_a = 0.0;
-        let y_a = -self.size.y / 2.0;
-        let x_b = self.size.x / 2.0;
-        let y_b = -self.size.y / 4.0;
-        let tri_top = Triangle::new(
-            self.pos + Vector::new(-x_b, y_b),
-            self.pos + Vector::new(x_a, y_a),
-            self.pos + Vector::new(x_b, y_b),
-        );
-        let tri_mid_a = Triangle::new(
-            self.pos + Vector::new(-x_b, y_b),
-            self.pos + Vector::new(x_b, y_b),
-            self.pos + Vector::new(x_b, -y_b),
-        );
-        let tri_mid_b = Triangle::new(
-            self.pos + Vector::new(x_b, -y_b),
-            self.pos + Vector::new(-x_b, -y_b),
-            self.pos + Vector::new(-x_b, y_b),
-        );
-        let tri_bot = Triangle::new(
-            self.pos + Vector::new(x_b, -y_b),
-            self.pos + Vector::new(-x_a, -y_a),
-            self.pos + Vector::new(-x_b, -y_b),
-        );
-        tri_top.draw(mesh, background, transform, z);
-        tri_mid_a.draw(mesh, background, transform, z);
-        tri_mid_b.draw(mesh, background, transform, z);
-        tri_bot.draw(mesh, background, transform, z);
+        // A hexagon rendered as a fan of 4 triangles from its first corner
+        let c = Layout::new(self.orientation, self.size, self.pos).corners(&Hex::new(0, 0, 0));
+        for i in 1..5 {
+            Triangle::new(c[0], c[i], c[i + 1]).draw(mesh, background, transform, z);
+        }
     }
 }
 
@@ -185,4 +805,305 @@ mod test {
                 .join(" ")
         );
     }
+
+    #[test]
+    fn neighbours_and_diagonals() {
+        let c = Hex::new(3, -7, 4);
+        for n in c.neighbours().iter() {
+            assert_eq!(n.x + n.y + n.z, 0);
+            assert_eq!(c.distance(n), 1);
+        }
+        for d in c.diagonals().iter() {
+            assert_eq!(d.x + d.y + d.z, 0);
+            assert_eq!(c.distance(d), 2);
+            // each diagonal sits between two neighbours
+            assert_eq!(
+                d.neighbours().iter().filter(|n| c.distance(n) == 1).count(),
+                2
+            );
+        }
+        assert_eq!(c.neighbour(6), c.neighbour(0));
+    }
+
+    #[test]
+    fn rings_and_spirals() {
+        let c = Hex::new(-2, 5, -3);
+        assert_eq!(HexRingIterator::new(c, 0).collect::<Vec<_>>(), vec![c]);
+        for radius in 1..6 {
+            let ring: Vec<Hex> = HexRingIterator::new(c, radius).collect();
+            assert_eq!(ring.len(), 6 * radius as usize);
+            assert!(ring.iter().all(|h| c.distance(h) == radius));
+            for (i, h) in ring.iter().enumerate() {
+                assert_eq!(h.distance(&ring[(i + 1) % ring.len()]), 1);
+            }
+        }
+        let spiral: Vec<Hex> = HexSpiralIterator::new(c, 4).collect();
+        assert_eq!(spiral.len(), 3 * 4 * 5 + 1);
+        let mut sorted: Vec<Hex> = HexManhattanIterator::new(4).map(|h| h + c).collect();
+        sorted.sort_by_key(|h| (h.x, h.y));
+        let mut spiral_sorted = spiral.clone();
+        spiral_sorted.sort_by_key(|h| (h.x, h.y));
+        assert_eq!(spiral_sorted, sorted);
+    }
+
+    #[test]
+    fn lines() {
+        let a = Hex::new(0, 0, 0);
+        assert_eq!(a.line_to(&a), vec![a]);
+        let b = Hex::new(5, -2, -3);
+        let line = a.line_to(&b);
+        assert_eq!(line.len(), 6);
+        assert_eq!(line[0], a);
+        assert_eq!(line[5], b);
+        for pair in line.windows(2) {
+            assert_eq!(pair[0].distance(&pair[1]), 1);
+        }
+        assert_eq!(
+            Hex::new(0, 0, 0).line_to(&Hex::new(0, -3, 3)),
+            vec![
+                Hex::new(0, 0, 0),
+                Hex::new(0, -1, 1),
+                Hex::new(0, -2, 2),
+                Hex::new(0, -3, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn rotation_and_reflection() {
+        let c = Hex::new(1, 1, -2);
+        let h = Hex::new(4, -1, -3);
+        assert_eq!(h.rotate(&c, 6), h);
+        assert_eq!(h.rotate(&c, 1).rotate(&c, -1), h);
+        assert_eq!(h.rotate(&c, 2), h.rotate(&c, -4));
+        assert_eq!(
+            Hex::new(1, 0, -1).rotate(&Hex::new(0, 0, 0), 1),
+            Hex::new(1, -1, 0)
+        );
+        for step in 0..6 {
+            assert_eq!(h.rotate(&c, step).distance(&c), h.distance(&c));
+        }
+        assert_eq!(h.reflect_x(&c).reflect_x(&c), h);
+        assert_eq!(h.reflect_y(&c).reflect_y(&c), h);
+        assert_eq!(h.reflect_z(&c).reflect_z(&c), h);
+        assert_eq!(h.reflect_x(&c).x, h.x);
+        assert_eq!(h.reflect_y(&c).y, h.y);
+        assert_eq!(h.reflect_z(&c).z, h.z);
+    }
+
+    #[test]
+    fn range_intersection() {
+        let a = Hex::new(0, 0, 0);
+        let b = Hex::new(3, -1, -2);
+        let range = HexRange::new(a, 3).intersection(&HexRange::new(b, 2));
+        let mut expected: Vec<Hex> = HexManhattanIterator::new(3)
+            .filter(|h| h.distance(&b) <= 2)
+            .collect();
+        let mut actual: Vec<Hex> = range.iter().collect();
+        expected.sort_by_key(|h| (h.x, h.y));
+        actual.sort_by_key(|h| (h.x, h.y));
+        assert_eq!(actual, expected);
+        assert!(actual.iter().all(|h| range.contains(h)));
+        assert_eq!(
+            HexRange::new(a, 1)
+                .intersection(&HexRange::new(Hex::new(5, 0, -5), 1))
+                .iter()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn fractional_rounding() {
+        assert_eq!(
+            hex_round(&FractionalHex::new(0.4, -0.3, -0.1)),
+            Hex::new(0, 0, 0)
+        );
+        assert_eq!(
+            hex_round(&FractionalHex::new(0.7, -0.1, -0.6)),
+            Hex::new(1, 0, -1)
+        );
+        assert_eq!(
+            hex_round(&FractionalHex::new(-2.6, 1.2, 1.4)),
+            Hex::new(-3, 1, 2)
+        );
+        for h in HexManhattanIterator::new(20) {
+            assert_eq!(hex_round(&to_fractional(&h)), h);
+        }
+    }
+
+    #[test]
+    fn pick_round_trips_drawn_hexes() {
+        let origin = Vector::new(500, 400);
+        let sizes = vec![
+            Vector::new(5.0 * 3.0f32.sqrt(), 10.0),
+            Vector::new(12.0, 9.0),
+            Vector::new(1.0, 1.0),
+        ];
+        for size in sizes {
+            // offsets from the centre towards the corners and edge midpoints of the drawn shape
+            let corners = vec![
+                Vector::new(0.0, -size.y / 2.0),
+                Vector::new(size.x / 2.0, -size.y / 4.0),
+                Vector::new(size.x / 2.0, size.y / 4.0),
+                Vector::new(0.0, size.y / 2.0),
+                Vector::new(-size.x / 2.0, size.y / 4.0),
+                Vector::new(-size.x / 2.0, -size.y / 4.0),
+                Vector::new(size.x / 2.0, 0.0),
+                Vector::new(-size.x / 2.0, 0.0),
+                Vector::new(size.x / 4.0, -size.y * 3.0 / 8.0),
+                Vector::new(-size.x / 4.0, size.y * 3.0 / 8.0),
+            ];
+            for h in HexManhattanIterator::new(60) {
+                let shape = HexShape::with_size_on_grid(h, origin, size);
+                assert_eq!(HexShape::pick_on_grid(shape.pos, origin, size), h);
+                for corner in corners.iter() {
+                    let inside = shape.pos + *corner * 0.98;
+                    assert_eq!(HexShape::pick_on_grid(inside, origin, size), h);
+                }
+            }
+        }
+        let shape = HexShape::with_radius_on_grid(Hex::new(3, -5, 2), origin, 5.0);
+        assert_eq!(
+            HexShape::pick_on_grid_with_radius(shape.pos, origin, 5.0),
+            Hex::new(3, -5, 2)
+        );
+    }
+
+    #[test]
+    fn offset_and_axial_round_trip() {
+        let kinds = [
+            OffsetKind::OddR,
+            OffsetKind::EvenR,
+            OffsetKind::OddQ,
+            OffsetKind::EvenQ,
+        ];
+        for h in HexManhattanIterator::new(10) {
+            assert_eq!(Axial::from_hex(&h).to_hex(), h);
+            for kind in kinds.iter() {
+                assert_eq!(OffsetCoord::from_hex(&h, *kind).to_hex(*kind), h);
+            }
+        }
+        let h = Hex::new(-1, 3, -2);
+        assert_eq!(
+            OffsetCoord::from_hex(&h, OffsetKind::OddR),
+            OffsetCoord::new(0, 3)
+        );
+        assert_eq!(
+            OffsetCoord::from_hex(&h, OffsetKind::EvenR),
+            OffsetCoord::new(1, 3)
+        );
+        assert_eq!(
+            OffsetCoord::from_hex(&h, OffsetKind::OddQ),
+            OffsetCoord::new(-1, 2)
+        );
+        assert_eq!(
+            OffsetCoord::from_hex(&h, OffsetKind::EvenQ),
+            OffsetCoord::new(-1, 3)
+        );
+    }
+
+    #[test]
+    fn rectangles_cover_a_screen_rectangle() {
+        for (kind, orientation) in [
+            (OffsetKind::OddR, Orientation::POINTY),
+            (OffsetKind::EvenR, Orientation::POINTY),
+            (OffsetKind::OddQ, Orientation::FLAT),
+            (OffsetKind::EvenQ, Orientation::FLAT),
+        ]
+        .iter()
+        {
+            let hexes: Vec<Hex> = HexRectangleIterator::new(*kind, 7, 5).collect();
+            assert_eq!(hexes.len(), 35);
+            assert_eq!(HexRectangleIterator::new(*kind, 7, 5).len(), 35);
+            let layout = Layout::new(*orientation, Vector::new(1.0, 1.0), Vector::ZERO);
+            for (i, h) in hexes.iter().enumerate() {
+                let offset = OffsetCoord::from_hex(h, *kind);
+                assert_eq!((offset.row * 7 + offset.col) as usize, i);
+                // every row or column stays within half a hex of a straight line
+                let centre = layout.to_screen(h);
+                match kind {
+                    OffsetKind::OddR | OffsetKind::EvenR => {
+                        assert!((centre.x - offset.col as f32).abs() <= 0.5)
+                    }
+                    _ => assert!((centre.y - offset.row as f32).abs() <= 0.5),
+                }
+            }
+        }
+        assert_eq!(HexRectangleIterator::new(OffsetKind::OddR, 0, 5).count(), 0);
+    }
+
+    #[test]
+    fn wrapping_grids() {
+        let grid = WrappingGrid::new(OffsetKind::OddR, 10, 6, WrapMode::Cylinder);
+        let west = OffsetCoord::new(0, 3).to_hex(OffsetKind::OddR);
+        let east = OffsetCoord::new(9, 3).to_hex(OffsetKind::OddR);
+        assert_eq!(grid.distance(&west, &east), 1);
+        assert!(grid.neighbours(&west).contains(&east));
+        assert!(grid.contains(&(east + hex_direction(0))));
+        assert_eq!(grid.wrap(&(east + hex_direction(0))), west);
+
+        // the poles do not wrap on a cylinder
+        let north = OffsetCoord::new(4, 0).to_hex(OffsetKind::OddR);
+        let south = OffsetCoord::new(4, 5).to_hex(OffsetKind::OddR);
+        assert_eq!(grid.distance(&north, &south), 5);
+        assert!(!grid.contains(&(north + hex_direction(2))));
+
+        let torus = WrappingGrid::new(OffsetKind::OddR, 10, 6, WrapMode::Torus);
+        assert_eq!(torus.distance(&north, &south), 1);
+        for h in HexRectangleIterator::new(OffsetKind::OddR, 10, 6) {
+            for n in torus.neighbours(&h).iter() {
+                assert!(torus.contains(n));
+                assert_eq!(torus.wrap(n), *n);
+                assert_eq!(torus.distance(&h, n), 1);
+            }
+            assert_eq!(torus.wrap(&h), h);
+        }
+
+        let columns = WrappingGrid::new(OffsetKind::EvenQ, 8, 5, WrapMode::Cylinder);
+        for h in HexRectangleIterator::new(OffsetKind::EvenQ, 8, 5) {
+            for n in columns.neighbours(&h).iter() {
+                if columns.contains(n) {
+                    assert_eq!(columns.distance(&h, n), 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn odd_torus_is_refused() {
+        WrappingGrid::new(OffsetKind::OddR, 10, 5, WrapMode::Torus);
+    }
+
+    #[test]
+    fn layouts_tile_and_pick() {
+        let origin = Vector::new(-30, 70);
+        for orientation in [Orientation::POINTY, Orientation::FLAT].iter() {
+            let layout = Layout::with_radius(*orientation, 7.0, origin);
+            for h in HexManhattanIterator::new(30) {
+                let centre = layout.to_screen(&h);
+                assert_eq!(layout.pick(centre), h);
+                for corner in layout.corners(&h).iter() {
+                    let inside = centre + (*corner - centre) * 0.98;
+                    assert_eq!(layout.pick(inside), h);
+                }
+                // neighbours share two corners with the hex
+                for n in h.neighbours().iter() {
+                    let shared = layout
+                        .corners(n)
+                        .iter()
+                        .filter(|a| layout.corners(&h).iter().any(|b| (**a - *b).len() < 1e-3))
+                        .count();
+                    assert_eq!(shared, 2);
+                }
+            }
+        }
+        let flat = Layout::with_radius(Orientation::FLAT, 2.0, Vector::ZERO);
+        assert_eq!(
+            flat.to_screen(&Hex::new(0, 1, -1)),
+            Vector::new(0.0, 2.0 * 3.0f32.sqrt())
+        );
+        assert_eq!(flat.to_screen(&Hex::new(1, 0, -1)).x, 3.0);
+    }
 }
@@ -0,0 +1,4 @@
+pub mod game;
+pub mod geometry;
+pub mod procgen;
+pub mod render;
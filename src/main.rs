@@ -1,33 +1,250 @@
-#[macro_use]
-extern crate derive_more;
-mod game;
-mod geometry;
-mod procgen;
-use game::world::GameWorld;
-use procgen::noise::Noise;
+use nations::game::knowledge::{Knowledge, Visibility};
+use nations::game::vision::{field_of_view, TerrainSight};
+use nations::game::world::cache::ChunkCache;
+use nations::game::world::hex_map::HexMapShape;
+use nations::game::world::Wrapped;
+use nations::geometry::{Hex, Layout, OffsetCoord, OffsetKind, Orientation, WrapMode};
+use nations::procgen::terrain::{Terrain, TerrainSample};
+use nations::{game, procgen};
 
 use quicksilver::{
     geom::{Rectangle, Vector},     // We'll need to import Rectangle now
     graphics::{Background, Color}, // Also Background and Color
-    lifecycle::{run, State, Window},
+    input::{ButtonState, Key, MouseButton},
+    lifecycle::{run, Event, State, Window},
     Result,
 };
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+const HEX_RADIUS: f32 = 5.0;
+// as many hexes as fit in the window
+const MAP_WIDTH: usize = 112;
+const MAP_HEIGHT: usize = 130;
+const SIGHT_RADIUS: isize = 8;
+const SCROLL_STEP: isize = 8;
+const EYE_HEIGHT: f64 = 0.1;
+const CHUNK_SIZE: usize = 16;
+const CACHE_BYTES: usize = 16 << 20;
+
+/// The centre of the top left hex, so that the whole of it is drawn.
+fn grid_origin() -> Vector {
+    Orientation::POINTY.size_with_radius(HEX_RADIUS) * 0.5
+}
+
+fn layout() -> Layout {
+    Layout::with_radius(Orientation::POINTY, HEX_RADIUS, grid_origin())
+}
+
+struct Nation {
+    name: &'static str,
+    scout: Hex,
+    knowledge: Knowledge<TerrainSample>,
+}
+
+impl Nation {
+    fn new(name: &'static str, scout: OffsetCoord, shape: HexMapShape) -> Nation {
+        Nation {
+            name: name,
+            scout: scout.to_hex(OffsetKind::OddR),
+            knowledge: Knowledge::new(shape),
+        }
+    }
+}
 
 struct Screen {
     seed: procgen::procseed::ProcSeed,
     world: game::world::TiledGameWorld,
+    hovered: Option<Hex>,
+    selected: Option<Hex>,
+    nations: Vec<Nation>,
+    active: usize,
+    turn: u64,
+    /// Columns the view is scrolled east around the world.
+    scroll: isize,
+    /// Whether the skew drifts every update, as it does from the start. Drifting regenerates the
+    /// world every frame, so the colour cache only pays off while it is paused.
+    animate: bool,
+    colours: ChunkCache<Color>,
+}
+
+impl Screen {
+    /// The world hex drawn at a position of the view.
+    fn to_world(&self, view: &Hex) -> Hex {
+        self.world
+            .get_grid()
+            .wrap(&(view + Hex::new(self.scroll, 0, -self.scroll)))
+    }
+
+    /// The position of the view a world hex is drawn at.
+    fn to_view(&self, hex: &Hex) -> Hex {
+        self.world
+            .get_grid()
+            .wrap(&(hex - Hex::new(self.scroll, 0, -self.scroll)))
+    }
+
+    /// Describes a hex as the active nation knows it.
+    fn describe(&self, hex: Hex) -> String {
+        let knowledge = &self.nations[self.active].knowledge;
+        let (sample, when) = match knowledge.get_visibility(&hex) {
+            Visibility::Unexplored => {
+                return format!("({}, {}, {}): unexplored", hex.x, hex.y, hex.z);
+            }
+            Visibility::Remembered => {
+                let memory = knowledge.get_memory(&hex).unwrap();
+                (
+                    memory.snapshot,
+                    format!(" (as seen on turn {})", memory.turn),
+                )
+            }
+            Visibility::Visible => (
+                self.world
+                    .get_terrain()
+                    .get_sample(&self.seed, &self.world.get_hex_point(&hex)),
+                String::new(),
+            ),
+        };
+        format!(
+            "({}, {}, {}): {:?}, height {:.3}, temperature {:.3}, precipitation {:.3}{}",
+            hex.x,
+            hex.y,
+            hex.z,
+            sample.biome,
+            sample.height,
+            sample.temperature,
+            sample.precipitation,
+            when
+        )
+    }
+
+    /// Updates what every nation can see from where its scout stands. Only the tiles the scouts
+    /// look at are sampled, each once however many scouts look at it.
+    fn look(&mut self) {
+        let world = &self.world;
+        let seed = &self.seed;
+        let shape = world.get_tile_shape();
+        let samples = RefCell::new(HashMap::new());
+        let tile = |hex: &Hex| {
+            if !shape.contains(hex) {
+                return None;
+            }
+            let mut samples = samples.borrow_mut();
+            let sample = samples.entry(*hex).or_insert_with(|| {
+                world
+                    .get_terrain()
+                    .get_sample(seed, &world.get_hex_point(hex))
+            });
+            Some(*sample)
+        };
+        let water_level = world.get_terrain().get_biome_table().water_level;
+        let sight = Wrapped {
+            grid: world.get_grid(),
+            inner: TerrainSight::new(&tile, water_level),
+        };
+        for nation in self.nations.iter_mut() {
+            let radius = sight.inner.sight_radius(&nation.scout, SIGHT_RADIUS);
+            let visible = field_of_view(&nation.scout, radius, EYE_HEIGHT, &sight);
+            nation
+                .knowledge
+                .see(visible, self.turn, |hex| tile(hex).unwrap());
+        }
+    }
+
+    /// Remembered hexes are drawn dimmed from their snapshot rather than the live terrain.
+    fn remembered_colour(&self, sample: &TerrainSample) -> Color {
+        let style = self
+            .world
+            .get_terrain()
+            .get_biome_table()
+            .style(sample.biome);
+        Color {
+            r: style.colour[0] as f32 * 0.4,
+            g: style.colour[1] as f32 * 0.4,
+            b: style.colour[2] as f32 * 0.4,
+            a: 1.0,
+        }
+    }
 }
 
 impl State for Screen {
     fn new() -> Result<Screen> {
-        Ok(Screen {
-            seed: procgen::procseed::ProcSeed::new(&0u32, 0.0),
-            world: game::world::TiledGameWorld::new(1000, (1000.0 * 0.75) as usize),
-        })
+        // a shared seed string can be passed as the first argument
+        let seed = match std::env::args().nth(1) {
+            Some(text) => text.parse().unwrap_or_else(|e| {
+                eprintln!("ignoring seed {:?}: {}", text, e);
+                procgen::procseed::ProcSeed::new(&0u32, 0.0)
+            }),
+            None => procgen::procseed::ProcSeed::new(&0u32, 0.0),
+        };
+        println!("seed: {} (press S to print the current seed)", seed);
+        println!("right click to move the scout, tab to switch nation, left and right to scroll");
+        println!("space to stop or restart the terrain drifting");
+        let mut world = game::world::TiledGameWorld::new(MAP_WIDTH, MAP_HEIGHT);
+        world.set_wrap(WrapMode::Cylinder);
+        let (col, row) = (MAP_WIDTH as isize / 5, MAP_HEIGHT as isize / 2);
+        let mut screen = Screen {
+            seed: seed,
+            nations: vec![
+                Nation::new("west", OffsetCoord::new(col, row), world.get_tile_shape()),
+                Nation::new(
+                    "east",
+                    OffsetCoord::new(4 * col, row),
+                    world.get_tile_shape(),
+                ),
+            ],
+            world: world,
+            hovered: None,
+            selected: None,
+            active: 0,
+            turn: 0,
+            scroll: 0,
+            animate: true,
+            colours: ChunkCache::new(CHUNK_SIZE, CACHE_BYTES),
+        };
+        screen.look();
+        Ok(screen)
     }
 
     fn update(&mut self, _window: &mut Window) -> Result<()> {
-        self.seed.skew += 0.005;
+        if self.animate {
+            self.seed.skew += 0.005;
+        }
+        Ok(())
+    }
+
+    fn event(&mut self, event: &Event, _window: &mut Window) -> Result<()> {
+        match event {
+            Event::Key(Key::S, ButtonState::Pressed) => println!("seed: {}", self.seed),
+            Event::Key(Key::Tab, ButtonState::Pressed) => {
+                self.active = (self.active + 1) % self.nations.len();
+                println!("playing as {}", self.nations[self.active].name);
+            }
+            Event::Key(Key::Space, ButtonState::Pressed) => self.animate = !self.animate,
+            Event::Key(Key::Left, ButtonState::Pressed) => self.scroll -= SCROLL_STEP,
+            Event::Key(Key::Right, ButtonState::Pressed) => self.scroll += SCROLL_STEP,
+            Event::MouseMoved(pos) => {
+                let view = layout().pick(*pos);
+                self.hovered = if self.world.get_tile_shape().contains(&view) {
+                    Some(self.to_world(&view))
+                } else {
+                    None
+                };
+            }
+            Event::MouseButton(MouseButton::Left, ButtonState::Pressed) => {
+                self.selected = self.hovered;
+                if let Some(hex) = self.selected {
+                    println!("{}", self.describe(hex));
+                }
+            }
+            Event::MouseButton(MouseButton::Right, ButtonState::Pressed) => {
+                if let Some(hex) = self.hovered {
+                    self.nations[self.active].scout = hex;
+                    self.turn += 1;
+                    self.look();
+                }
+            }
+            _ => (),
+        }
         Ok(())
     }
 
@@ -35,26 +252,54 @@ impl State for Screen {
         // Clear the contents of the window to a white background
         window.clear(Color::WHITE)?;
 
-        geometry::HexManhattanIterator::new(50)
-            .map(|x| geometry::HexShape::with_radius_on_grid(x, Vector::new(500, 500), 5.0))
-            .enumerate()
-            .for_each(|(_i, x)| {
-                window.draw(
-                    &x,
-                    Background::Col(
-                        self.world
-                            .render_qs(
-                                &self.seed,
-                                &game::world::GameWorldOffset::new(
-                                    (x.pos.x - 500.0) as i64,
-                                    (x.pos.y - 500.0) as i64,
-                                    0,
-                                ),
-                            )
-                            .1,
-                    ),
-                )
-            });
+        let world = &self.world;
+        let seed = &self.seed;
+        let knowledge = &self.nations[self.active].knowledge;
+        self.colours.set_generation(world.get_generation());
+        for view in world.get_tile_shape().hexes() {
+            let shape = layout().shape(&view);
+            let hex = self.to_world(&view);
+            let colour = match knowledge.get_visibility(&hex) {
+                Visibility::Unexplored => Color::BLACK,
+                Visibility::Remembered => {
+                    self.remembered_colour(&knowledge.get_memory(&hex).unwrap().snapshot)
+                }
+                Visibility::Visible => *self.colours.get(seed, &hex, |h| {
+                    let point = world.get_hex_point(h);
+                    world.get_terrain().render_qs(seed, &point).1
+                }),
+            };
+            window.draw(&shape, Background::Col(colour))
+        }
+
+        window.draw(
+            &layout().shape(&self.to_view(&self.nations[self.active].scout)),
+            Background::Col(Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            }),
+        );
+
+        if let Some(hex) = self.selected {
+            window.draw(
+                &layout().shape(&self.to_view(&hex)),
+                Background::Col(Color {
+                    a: 0.6,
+                    ..Color::BLACK
+                }),
+            );
+        }
+        if let Some(hex) = self.hovered {
+            window.draw(
+                &layout().shape(&self.to_view(&hex)),
+                Background::Col(Color {
+                    a: 0.5,
+                    ..Color::WHITE
+                }),
+            );
+        }
 
         Ok(())
     }
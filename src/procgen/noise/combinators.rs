@@ -0,0 +1,388 @@
+use super::{batch_len, torus_axes, torus_derivative, torus_point, Noise, NoiseDerivative};
+use crate::procgen::procseed::ProcSeed;
+
+// Combinators pass the seed they are given to their main `source` unchanged. Other inputs get
+// the seed derived from their field name, so that the same noise used twice in one graph does
+// not give the same values twice.
+
+/// The same value everywhere.
+pub struct Constant {
+    pub value: f64,
+}
+
+impl Noise for Constant {
+    fn get_noise(&self, _seed: &ProcSeed, _point: &Vec<f64>) -> f64 {
+        self.value
+    }
+
+    fn get_noise_batch(&self, _seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        vec![self.value; batch_len(axes)]
+    }
+}
+
+/// Samples `source` with the seed derived from `key`, so that graphs can give otherwise
+/// identical branches their own seeds, or reproduce the seeds of hand-written layers.
+pub struct Derive<N> {
+    pub source: N,
+    pub key: String,
+}
+
+impl<N: Noise> Noise for Derive<N> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        self.source.get_noise(&seed.derive(&self.key), point)
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        self.source.get_noise_batch(&seed.derive(&self.key), axes)
+    }
+}
+
+/// Samples `source` at the point multiplied by `frequency`.
+pub struct ScalePoint<N> {
+    pub source: N,
+    pub frequency: f64,
+}
+
+impl<N: Noise> Noise for ScalePoint<N> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        let scaled = point.iter().map(|x| x * self.frequency).collect();
+        self.source.get_noise(seed, &scaled)
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        let scaled: Vec<Vec<f64>> = axes
+            .iter()
+            .map(|axis| axis.iter().map(|x| x * self.frequency).collect())
+            .collect();
+        self.source.get_noise_batch(seed, &scaled)
+    }
+}
+
+/// Multiplies the noise by `factor`.
+pub struct Scale<N> {
+    pub source: N,
+    pub factor: f64,
+}
+
+impl<N: Noise> Noise for Scale<N> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        self.source.get_noise(seed, point) * self.factor
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        let mut noise = self.source.get_noise_batch(seed, axes);
+        for n in noise.iter_mut() {
+            *n *= self.factor;
+        }
+        noise
+    }
+}
+
+/// Adds `offset` to the noise.
+pub struct Offset<N> {
+    pub source: N,
+    pub offset: f64,
+}
+
+impl<N: Noise> Noise for Offset<N> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        self.source.get_noise(seed, point) + self.offset
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        let mut noise = self.source.get_noise_batch(seed, axes);
+        for n in noise.iter_mut() {
+            *n += self.offset;
+        }
+        noise
+    }
+}
+
+/// Limits the noise to `min..=max`.
+pub struct Clamp<N> {
+    pub source: N,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl<N: Noise> Noise for Clamp<N> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        self.source
+            .get_noise(seed, point)
+            .max(self.min)
+            .min(self.max)
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        let mut noise = self.source.get_noise_batch(seed, axes);
+        for n in noise.iter_mut() {
+            *n = n.max(self.min).min(self.max);
+        }
+        noise
+    }
+}
+
+/// `low` where `control` is below `threshold` and `high` above it, blended smoothly across
+/// `falloff` either side of the threshold, or switching sharply if `falloff` is zero.
+pub struct Select<C, A, B> {
+    pub control: C,
+    pub low: A,
+    pub high: B,
+    pub threshold: f64,
+    pub falloff: f64,
+}
+
+impl<C, A, B> Select<C, A, B> {
+    /// How far towards `high` the noise is for a value of `control`.
+    fn weight(&self, control: f64) -> f64 {
+        if self.falloff > 0.0 {
+            let t = (control - self.threshold + self.falloff) / (2.0 * self.falloff);
+            let t = t.clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        } else if control < self.threshold {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+impl<C: Noise, A: Noise, B: Noise> Noise for Select<C, A, B> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        let t = self.weight(self.control.get_noise(&seed.derive(&"control"), point));
+        // only sample the sides that contribute
+        let low = if t < 1.0 {
+            self.low.get_noise(&seed.derive(&"low"), point)
+        } else {
+            0.0
+        };
+        let high = if t > 0.0 {
+            self.high.get_noise(&seed.derive(&"high"), point)
+        } else {
+            0.0
+        };
+        low + (high - low) * t
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        let t: Vec<f64> = self
+            .control
+            .get_noise_batch(&seed.derive(&"control"), axes)
+            .into_iter()
+            .map(|control| self.weight(control))
+            .collect();
+        // only sample a side if some point needs it, and leave it out where `get_noise` would
+        let low = if t.iter().any(|t| *t < 1.0) {
+            self.low.get_noise_batch(&seed.derive(&"low"), axes)
+        } else {
+            vec![0.0; t.len()]
+        };
+        let high = if t.iter().any(|t| *t > 0.0) {
+            self.high.get_noise_batch(&seed.derive(&"high"), axes)
+        } else {
+            vec![0.0; t.len()]
+        };
+        (0..t.len())
+            .map(|i| {
+                let low = if t[i] < 1.0 { low[i] } else { 0.0 };
+                let high = if t[i] > 0.0 { high[i] } else { 0.0 };
+                low + (high - low) * t[i]
+            })
+            .collect()
+    }
+}
+
+/// `a` and `b` mixed by `control`: all `a` at -1, all `b` at 1 and linearly in between.
+pub struct Blend<A, B, C> {
+    pub a: A,
+    pub b: B,
+    pub control: C,
+}
+
+impl<A: Noise, B: Noise, C: Noise> Noise for Blend<A, B, C> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        let a = self.a.get_noise(&seed.derive(&"a"), point);
+        let b = self.b.get_noise(&seed.derive(&"b"), point);
+        let t = (self.control.get_noise(&seed.derive(&"control"), point) + 1.0) / 2.0;
+        a + (b - a) * t.clamp(0.0, 1.0)
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        let a = self.a.get_noise_batch(&seed.derive(&"a"), axes);
+        let b = self.b.get_noise_batch(&seed.derive(&"b"), axes);
+        let control = self.control.get_noise_batch(&seed.derive(&"control"), axes);
+        (0..a.len())
+            .map(|i| {
+                let t = (control[i] + 1.0) / 2.0;
+                a[i] + (b[i] - a[i]) * t.clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// Samples `source` at a point pushed along each axis by `strength` times `warp`, which is
+/// sampled once per axis with the seed derived from `("warp", axis)`. Bends straight features
+/// into the swirls of eroded or folded terrain.
+pub struct DomainWarp<N, W> {
+    pub source: N,
+    pub warp: W,
+    pub strength: f64,
+}
+
+impl<N: Noise, W: Noise> Noise for DomainWarp<N, W> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        let warped = (0..point.len())
+            .map(|i| {
+                point[i] + self.strength * self.warp.get_noise(&seed.derive(&("warp", i)), point)
+            })
+            .collect();
+        self.source.get_noise(seed, &warped)
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        let warped: Vec<Vec<f64>> = (0..axes.len())
+            .map(|i| {
+                let warp = self.warp.get_noise_batch(&seed.derive(&("warp", i)), axes);
+                axes[i]
+                    .iter()
+                    .zip(warp)
+                    .map(|(x, w)| x + self.strength * w)
+                    .collect()
+            })
+            .collect();
+        self.source.get_noise_batch(seed, &warped)
+    }
+}
+
+/// Makes `source` repeat every `tile_distance` along each of the first `dim` axes by sampling
+/// it on a circle of radius `scale` in a pair of source axes per input axis, so `source` needs
+/// `2 * dim` dimensions. If `skewed`, the seed's skew is passed as one more axis, so that
+/// changing the skew morphs the noise smoothly.
+pub struct Tiled<N> {
+    pub source: N,
+    pub dim: usize,
+    pub tile_distance: f64,
+    pub scale: f64,
+    pub skewed: bool,
+}
+
+impl<N> Tiled<N> {
+    /// The point `source` is sampled at for `point`.
+    fn source_point(&self, seed: &ProcSeed, point: &Vec<f64>) -> Vec<f64> {
+        let mut point_source = torus_point(self.dim, self.tile_distance, self.scale, point);
+        if self.skewed {
+            point_source.push(seed.get_skew());
+        }
+        point_source
+    }
+}
+
+impl<N: Noise> Noise for Tiled<N> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        self.source.get_noise(seed, &self.source_point(seed, point))
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        let mut axes_source = torus_axes(self.dim, self.tile_distance, self.scale, axes);
+        if self.skewed {
+            axes_source.push(vec![seed.get_skew(); batch_len(axes)]);
+        }
+        self.source.get_noise_batch(seed, &axes_source)
+    }
+}
+
+impl<N: NoiseDerivative> NoiseDerivative for Tiled<N> {
+    fn get_noise_derivative(&self, seed: &ProcSeed, point: &Vec<f64>) -> Vec<f64> {
+        // the skew axis is not part of the caller's input space, so its partial is dropped
+        let der_source = self
+            .source
+            .get_noise_derivative(seed, &self.source_point(seed, point));
+        torus_derivative(self.dim, self.tile_distance, self.scale, point, &der_source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::fractal::Fractal;
+    use super::super::perlin_noise::PerlinNoise;
+    use super::super::simplex_noise::{OctavedSimplexNoise, TiledOctavedSimplexNoise};
+    use super::super::{test_points, to_axes};
+    use super::*;
+
+    #[test]
+    fn tiled_noise_repeats() {
+        let seed = ProcSeed::new(&"tiled", 0.3);
+        let layer = TiledOctavedSimplexNoise::skewed_octaved(2, 4, 0.5, 1.0, 0.5);
+        let new = Tiled {
+            source: OctavedSimplexNoise::new(5, 4, 0.5),
+            dim: 2,
+            tile_distance: 1.0,
+            scale: 0.5,
+            skewed: true,
+        };
+        for point in test_points(100, 3.0) {
+            let value = new.get_noise(&seed, &point);
+            assert_eq!(value.to_bits(), layer.get_noise(&seed, &point).to_bits());
+            let moved = vec![point[0] + 1.0, point[1] - 2.0];
+            assert!((new.get_noise(&seed, &moved) - value).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn graphs_compose() {
+        let seed = ProcSeed::new(&"graph", 0.0);
+        // ridged mountains where a mask is high, gentle hills elsewhere, with warped coasts
+        let graph = Clamp {
+            source: DomainWarp {
+                source: Select {
+                    control: ScalePoint {
+                        source: PerlinNoise::new(2),
+                        frequency: 0.5,
+                    },
+                    low: Scale {
+                        source: Fractal::fbm(PerlinNoise::new(2), 3),
+                        factor: 0.3,
+                    },
+                    high: Fractal::ridged(PerlinNoise::new(2), 4),
+                    threshold: 0.1,
+                    falloff: 0.2,
+                },
+                warp: Box::new(PerlinNoise::new(2)) as Box<dyn Noise>,
+                strength: 0.25,
+            },
+            min: -1.0,
+            max: 0.9,
+        };
+        let points = test_points(100, 3.0);
+        let batch = graph.get_noise_batch(&seed, &to_axes(&points));
+        for (point, value) in points.iter().zip(batch) {
+            let single = graph.get_noise(&seed, point);
+            assert_eq!(value, single);
+            assert!((-1.0..=0.9).contains(&single));
+        }
+    }
+
+    #[test]
+    fn select_and_blend_edges() {
+        let seed = ProcSeed::new(&"select", 0.0);
+        let point = vec![0.5, 0.5];
+        let select = |control, falloff| Select {
+            control: Constant { value: control },
+            low: Constant { value: -1.0 },
+            high: Constant { value: 1.0 },
+            threshold: 0.0,
+            falloff: falloff,
+        };
+        assert_eq!(select(0.5, 0.0).get_noise(&seed, &point), 1.0);
+        assert_eq!(select(-0.01, 0.0).get_noise(&seed, &point), -1.0);
+        assert_eq!(select(0.0, 0.5).get_noise(&seed, &point), 0.0);
+        assert_eq!(select(0.6, 0.5).get_noise(&seed, &point), 1.0);
+
+        let blend = Blend {
+            a: Constant { value: -1.0 },
+            b: Constant { value: 1.0 },
+            control: Constant { value: 0.5 },
+        };
+        assert_eq!(blend.get_noise(&seed, &point), 0.5);
+    }
+}
@@ -0,0 +1,207 @@
+use super::{batch_len, Noise};
+use crate::procgen::procseed::ProcSeed;
+use serde::{Deserialize, Serialize};
+use std::iter;
+
+/// How each octave of a `Fractal` is shaped before it is summed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FractalKind {
+    /// Fractional Brownian motion: octaves summed as they are.
+    Fbm,
+    /// Octaves folded with `2|n| - 1`, which gives rounded hills and sharp creases between them,
+    /// like clouds or rolling ground.
+    Billow,
+    /// Perlin's turbulence: the sum of `|n|`, from 0 to 1, for fire, marble veins and masks.
+    Turbulence,
+    /// Musgrave's ridged multifractal: octaves folded with `(1 - |n|)^2` so that zero crossings
+    /// become sharp ridges, each octave weighted by the one before so that fine detail gathers
+    /// on the ridges and the valleys between them stay smooth. For mountain ranges.
+    Ridged,
+}
+
+/// Sums octaves of `source`, each sampled at `lacunarity` times the frequency of the last and
+/// weighted by `gain` times its amplitude, and scales the sum back to the range of a single
+/// octave. Octave `i` is seeded with `seed.derive(&i)`. `OctavedSimplexNoise` is fBm of simplex
+/// noise, and steps through its octaves with `steps`.
+pub struct Fractal<N> {
+    pub source: N,
+    pub kind: FractalKind,
+    pub octaves: usize,
+    /// Frequency of the first octave.
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+
+impl<N: Noise> Fractal<N> {
+    /// Doubles the frequency and halves the amplitude with each octave.
+    pub fn new(source: N, kind: FractalKind, octaves: usize) -> Fractal<N> {
+        Fractal {
+            source: source,
+            kind: kind,
+            octaves: octaves,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    pub fn fbm(source: N, octaves: usize) -> Fractal<N> {
+        Fractal::new(source, FractalKind::Fbm, octaves)
+    }
+
+    pub fn billow(source: N, octaves: usize) -> Fractal<N> {
+        Fractal::new(source, FractalKind::Billow, octaves)
+    }
+
+    pub fn turbulence(source: N, octaves: usize) -> Fractal<N> {
+        Fractal::new(source, FractalKind::Turbulence, octaves)
+    }
+
+    pub fn ridged(source: N, octaves: usize) -> Fractal<N> {
+        Fractal::new(source, FractalKind::Ridged, octaves)
+    }
+
+    /// The frequency and amplitude of each octave in turn.
+    pub fn steps(&self) -> impl Iterator<Item = (f64, f64)> {
+        let (lacunarity, gain) = (self.lacunarity, self.gain);
+        iter::successors(
+            Some((self.frequency, 1.0)),
+            move |(frequency, amplitude)| Some((frequency * lacunarity, amplitude * gain)),
+        )
+        .take(self.octaves)
+    }
+
+    /// The sum of the octaves' amplitudes, which the octaves are divided by. Panics if it is
+    /// zero, as it is without octaves, since the noise would be NaN everywhere.
+    pub fn total_amplitude(&self) -> f64 {
+        let total = self.steps().map(|(_, amplitude)| amplitude).sum();
+        assert!(
+            total != 0.0,
+            "{} octaves with a gain of {} have no total amplitude to scale by",
+            self.octaves,
+            self.gain
+        );
+        total
+    }
+
+    /// Sums the octaves given the noise of each, which `octave(i, frequency)` samples at the
+    /// point scaled by `frequency`. Every point of a batch goes through the same arithmetic.
+    fn sum<F: FnMut(usize, f64) -> Vec<f64>>(&self, len: usize, mut octave: F) -> Vec<f64> {
+        let mut value = vec![0.0; len];
+        let mut weight = vec![1.0; len];
+        for (i, (frequency, amplitude)) in self.steps().enumerate() {
+            let noise = octave(i, frequency);
+            for j in 0..len {
+                let signal = match self.kind {
+                    FractalKind::Fbm => noise[j],
+                    FractalKind::Billow => 2.0 * noise[j].abs() - 1.0,
+                    FractalKind::Turbulence => noise[j].abs(),
+                    FractalKind::Ridged => {
+                        let ridge = (1.0 - noise[j].abs()).powi(2) * weight[j];
+                        weight[j] = (ridge * 2.0).clamp(0.0, 1.0);
+                        ridge
+                    }
+                };
+                value[j] += signal * amplitude;
+            }
+        }
+        let total = self.total_amplitude();
+        for v in value.iter_mut() {
+            *v /= total;
+            if self.kind == FractalKind::Ridged {
+                // ridges are summed from 0 to 1, so stretch them to the usual -1 to 1
+                *v = *v * 2.0 - 1.0;
+            }
+        }
+        value
+    }
+}
+
+impl<N: Noise> Noise for Fractal<N> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        let mut scaled = point.clone();
+        self.sum(1, |i, frequency| {
+            for (s, x) in scaled.iter_mut().zip(point) {
+                *s = x * frequency;
+            }
+            vec![self.source.get_noise(&seed.derive(&i), &scaled)]
+        })[0]
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        self.sum(batch_len(axes), |i, frequency| {
+            let scaled: Vec<Vec<f64>> = axes
+                .iter()
+                .map(|axis| axis.iter().map(|x| x * frequency).collect())
+                .collect();
+            self.source.get_noise_batch(&seed.derive(&i), &scaled)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::perlin_noise::PerlinNoise;
+    use super::super::{test_points, to_axes};
+    use super::*;
+
+    #[test]
+    fn kinds_stay_in_range() {
+        let seed = ProcSeed::new(&"fractal", 0.0);
+        for kind in [
+            FractalKind::Fbm,
+            FractalKind::Billow,
+            FractalKind::Turbulence,
+            FractalKind::Ridged,
+        ]
+        .iter()
+        {
+            let fractal = Fractal::new(PerlinNoise::new(2), *kind, 5);
+            let low = if *kind == FractalKind::Turbulence {
+                0.0
+            } else {
+                -1.0
+            };
+            for point in test_points(300, 10.0) {
+                let value = fractal.get_noise(&seed, &point);
+                assert!(low <= value && value <= 1.0, "{:?}: {}", kind, value);
+            }
+        }
+    }
+
+    #[test]
+    fn single_octave_shapes() {
+        let seed = ProcSeed::new(&"fractal", 0.0);
+        let source = PerlinNoise::new(2);
+        let billow = Fractal::billow(PerlinNoise::new(2), 1);
+        let ridged = Fractal::ridged(PerlinNoise::new(2), 1);
+        for point in test_points(300, 10.0) {
+            let n = source.get_noise(&seed.derive(&0usize), &point);
+            assert_eq!(billow.get_noise(&seed, &point), 2.0 * n.abs() - 1.0);
+            let expected = (1.0 - n.abs()).powi(2) * 2.0 - 1.0;
+            assert!((ridged.get_noise(&seed, &point) - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn batches_match_single_points() {
+        let seed = ProcSeed::new(&"fractal", 0.0);
+        let ridged = Fractal::ridged(PerlinNoise::new(2), 4);
+        let points = test_points(300, 10.0);
+        let batch = ridged.get_noise_batch(&seed, &to_axes(&points));
+        for (point, value) in points.iter().zip(batch) {
+            assert_eq!(value, ridged.get_noise(&seed, point));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn cancelling_octaves_are_refused() {
+        let fractal = Fractal {
+            gain: -1.0,
+            ..Fractal::fbm(PerlinNoise::new(2), 2)
+        };
+        fractal.get_noise(&ProcSeed::new(&"fractal", 0.0), &vec![0.3, 0.7]);
+    }
+}
@@ -0,0 +1,640 @@
+use super::combinators::{
+    Blend, Clamp, Constant, Derive, DomainWarp, Offset, Scale, ScalePoint, Select, Tiled,
+};
+use super::fractal::{Fractal, FractalKind};
+use super::perlin_noise::PerlinNoise;
+use super::simplex_noise::{OctavedSimplexNoise, SimplexNoise};
+use super::value_noise::ValueNoise;
+use super::worley_noise::{WorleyNoise, WorleyOutput};
+use super::Noise;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Noise that can be shared between the threads sampling a region.
+pub type BoxedNoise = Box<dyn Noise + Send + Sync>;
+
+/// A description of a noise graph that can be stored as RON or JSON and built into noise. Each
+/// node is named after the type it builds and has the same fields, with inputs as nested nodes,
+/// e.g. in RON:
+///
+/// ```text
+/// Tiled(
+///     dim: 2,
+///     tile_distance: 1.0,
+///     scale: 0.5,
+///     skewed: true,
+///     source: Fractal(kind: Ridged, octaves: 8, source: Simplex(dim: 5)),
+/// )
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum NoiseGraph {
+    Simplex {
+        dim: usize,
+    },
+    OctavedSimplex {
+        dim: usize,
+        octaves: usize,
+        octave_factor: f64,
+    },
+    Perlin {
+        dim: usize,
+    },
+    Value {
+        dim: usize,
+    },
+    Worley {
+        dim: usize,
+        output: WorleyOutput,
+    },
+    Constant {
+        value: f64,
+    },
+    Fractal {
+        source: Box<NoiseGraph>,
+        kind: FractalKind,
+        octaves: usize,
+        #[serde(default = "one")]
+        frequency: f64,
+        #[serde(default = "two")]
+        lacunarity: f64,
+        #[serde(default = "half")]
+        gain: f64,
+    },
+    Derive {
+        source: Box<NoiseGraph>,
+        key: String,
+    },
+    ScalePoint {
+        source: Box<NoiseGraph>,
+        frequency: f64,
+    },
+    Scale {
+        source: Box<NoiseGraph>,
+        factor: f64,
+    },
+    Offset {
+        source: Box<NoiseGraph>,
+        offset: f64,
+    },
+    Clamp {
+        source: Box<NoiseGraph>,
+        min: f64,
+        max: f64,
+    },
+    Select {
+        control: Box<NoiseGraph>,
+        low: Box<NoiseGraph>,
+        high: Box<NoiseGraph>,
+        threshold: f64,
+        #[serde(default)]
+        falloff: f64,
+    },
+    Blend {
+        a: Box<NoiseGraph>,
+        b: Box<NoiseGraph>,
+        control: Box<NoiseGraph>,
+    },
+    DomainWarp {
+        source: Box<NoiseGraph>,
+        warp: Box<NoiseGraph>,
+        strength: f64,
+    },
+    Tiled {
+        source: Box<NoiseGraph>,
+        dim: usize,
+        tile_distance: f64,
+        scale: f64,
+        #[serde(default)]
+        skewed: bool,
+    },
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn two() -> f64 {
+    2.0
+}
+
+fn half() -> f64 {
+    0.5
+}
+
+/// Why a `NoiseGraph` cannot be built into noise. Each names the node and field at fault.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphError {
+    /// A node works in more dimensions than the points it is given have.
+    Dimensions {
+        node: &'static str,
+        dim: usize,
+        input: usize,
+    },
+    /// A dimension, octave count or divisor is zero.
+    Zero {
+        node: &'static str,
+        field: &'static str,
+    },
+    /// A parameter is infinite or not a number.
+    NotFinite {
+        node: &'static str,
+        field: &'static str,
+    },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::Dimensions { node, dim, input } => write!(
+                f,
+                "{} needs {} dimensions but is given points with {}",
+                node, dim, input
+            ),
+            GraphError::Zero { node, field } => write!(f, "{} of {} must not be zero", field, node),
+            GraphError::NotFinite { node, field } => {
+                write!(f, "{} of {} must be a finite number", field, node)
+            }
+        }
+    }
+}
+
+impl Error for GraphError {}
+
+/// Checks that a node of `dim` dimensions can be given points with `input` coordinates.
+fn check_dim(node: &'static str, dim: usize, input: usize) -> Result<(), GraphError> {
+    if dim == 0 {
+        Err(GraphError::Zero {
+            node: node,
+            field: "dim",
+        })
+    } else if dim > input {
+        Err(GraphError::Dimensions {
+            node: node,
+            dim: dim,
+            input: input,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn check_finite(node: &'static str, field: &'static str, value: f64) -> Result<(), GraphError> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(GraphError::NotFinite {
+            node: node,
+            field: field,
+        })
+    }
+}
+
+/// Checks that `value` is finite and not zero, for parameters that are divided by.
+fn check_nonzero(node: &'static str, field: &'static str, value: f64) -> Result<(), GraphError> {
+    check_finite(node, field, value)?;
+    if value == 0.0 {
+        Err(GraphError::Zero {
+            node: node,
+            field: field,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn check_octaves(node: &'static str, octaves: usize) -> Result<(), GraphError> {
+    if octaves == 0 {
+        Err(GraphError::Zero {
+            node: node,
+            field: "octaves",
+        })
+    } else {
+        Ok(())
+    }
+}
+
+impl NoiseGraph {
+    /// The noise the graph describes, to be sampled at points with `input` coordinates, or why
+    /// it cannot be built.
+    pub fn build(&self, input: usize) -> Result<BoxedNoise, GraphError> {
+        self.check(input)?;
+        Ok(self.build_checked())
+    }
+
+    /// Checks that every node of the graph gets points with as many coordinates as it works
+    /// in, that no dimension, octave count or divisor is zero and that every parameter is
+    /// finite, so that the noise built from it neither panics nor gives NaN.
+    pub fn check(&self, input: usize) -> Result<(), GraphError> {
+        match self {
+            NoiseGraph::Simplex { dim } => check_dim("Simplex", *dim, input),
+            NoiseGraph::OctavedSimplex {
+                dim,
+                octaves,
+                octave_factor,
+            } => {
+                check_dim("OctavedSimplex", *dim, input)?;
+                check_octaves("OctavedSimplex", *octaves)?;
+                check_nonzero("OctavedSimplex", "octave_factor", *octave_factor)
+            }
+            NoiseGraph::Perlin { dim } => check_dim("Perlin", *dim, input),
+            NoiseGraph::Value { dim } => check_dim("Value", *dim, input),
+            NoiseGraph::Worley { dim, .. } => check_dim("Worley", *dim, input),
+            NoiseGraph::Constant { value } => check_finite("Constant", "value", *value),
+            NoiseGraph::Fractal {
+                source,
+                octaves,
+                frequency,
+                lacunarity,
+                gain,
+                ..
+            } => {
+                check_octaves("Fractal", *octaves)?;
+                check_finite("Fractal", "frequency", *frequency)?;
+                check_finite("Fractal", "lacunarity", *lacunarity)?;
+                check_finite("Fractal", "gain", *gain)?;
+                source.check(input)
+            }
+            NoiseGraph::Derive { source, .. } => source.check(input),
+            NoiseGraph::ScalePoint { source, frequency } => {
+                check_finite("ScalePoint", "frequency", *frequency)?;
+                source.check(input)
+            }
+            NoiseGraph::Scale { source, factor } => {
+                check_finite("Scale", "factor", *factor)?;
+                source.check(input)
+            }
+            NoiseGraph::Offset { source, offset } => {
+                check_finite("Offset", "offset", *offset)?;
+                source.check(input)
+            }
+            NoiseGraph::Clamp { source, min, max } => {
+                check_finite("Clamp", "min", *min)?;
+                check_finite("Clamp", "max", *max)?;
+                source.check(input)
+            }
+            NoiseGraph::Select {
+                control,
+                low,
+                high,
+                threshold,
+                falloff,
+            } => {
+                check_finite("Select", "threshold", *threshold)?;
+                check_finite("Select", "falloff", *falloff)?;
+                control.check(input)?;
+                low.check(input)?;
+                high.check(input)
+            }
+            NoiseGraph::Blend { a, b, control } => {
+                a.check(input)?;
+                b.check(input)?;
+                control.check(input)
+            }
+            NoiseGraph::DomainWarp {
+                source,
+                warp,
+                strength,
+            } => {
+                check_finite("DomainWarp", "strength", *strength)?;
+                source.check(input)?;
+                warp.check(input)
+            }
+            NoiseGraph::Tiled {
+                source,
+                dim,
+                tile_distance,
+                scale,
+                skewed,
+            } => {
+                check_dim("Tiled", *dim, input)?;
+                check_nonzero("Tiled", "tile_distance", *tile_distance)?;
+                check_finite("Tiled", "scale", *scale)?;
+                // a circle in a pair of source axes per input axis, and the skew after them
+                source.check(*dim * 2 + *skewed as usize)
+            }
+        }
+    }
+
+    /// The noise of a graph `check` has passed.
+    fn build_checked(&self) -> BoxedNoise {
+        match self {
+            NoiseGraph::Simplex { dim } => Box::new(SimplexNoise::new(*dim)),
+            NoiseGraph::OctavedSimplex {
+                dim,
+                octaves,
+                octave_factor,
+            } => Box::new(OctavedSimplexNoise::new(*dim, *octaves, *octave_factor)),
+            NoiseGraph::Perlin { dim } => Box::new(PerlinNoise::new(*dim)),
+            NoiseGraph::Value { dim } => Box::new(ValueNoise::new(*dim)),
+            NoiseGraph::Worley { dim, output } => Box::new(WorleyNoise::new(*dim, *output)),
+            NoiseGraph::Constant { value } => Box::new(Constant { value: *value }),
+            NoiseGraph::Fractal {
+                source,
+                kind,
+                octaves,
+                frequency,
+                lacunarity,
+                gain,
+            } => Box::new(Fractal {
+                source: source.build_checked(),
+                kind: *kind,
+                octaves: *octaves,
+                frequency: *frequency,
+                lacunarity: *lacunarity,
+                gain: *gain,
+            }),
+            NoiseGraph::Derive { source, key } => Box::new(Derive {
+                source: source.build_checked(),
+                key: key.clone(),
+            }),
+            NoiseGraph::ScalePoint { source, frequency } => Box::new(ScalePoint {
+                source: source.build_checked(),
+                frequency: *frequency,
+            }),
+            NoiseGraph::Scale { source, factor } => Box::new(Scale {
+                source: source.build_checked(),
+                factor: *factor,
+            }),
+            NoiseGraph::Offset { source, offset } => Box::new(Offset {
+                source: source.build_checked(),
+                offset: *offset,
+            }),
+            NoiseGraph::Clamp { source, min, max } => Box::new(Clamp {
+                source: source.build_checked(),
+                min: *min,
+                max: *max,
+            }),
+            NoiseGraph::Select {
+                control,
+                low,
+                high,
+                threshold,
+                falloff,
+            } => Box::new(Select {
+                control: control.build_checked(),
+                low: low.build_checked(),
+                high: high.build_checked(),
+                threshold: *threshold,
+                falloff: *falloff,
+            }),
+            NoiseGraph::Blend { a, b, control } => Box::new(Blend {
+                a: a.build_checked(),
+                b: b.build_checked(),
+                control: control.build_checked(),
+            }),
+            NoiseGraph::DomainWarp {
+                source,
+                warp,
+                strength,
+            } => Box::new(DomainWarp {
+                source: source.build_checked(),
+                warp: warp.build_checked(),
+                strength: *strength,
+            }),
+            NoiseGraph::Tiled {
+                source,
+                dim,
+                tile_distance,
+                scale,
+                skewed,
+            } => Box::new(Tiled {
+                source: source.build_checked(),
+                dim: *dim,
+                tile_distance: *tile_distance,
+                scale: *scale,
+                skewed: *skewed,
+            }),
+        }
+    }
+
+    /// Every input of this node.
+    fn inputs_mut(&mut self) -> Vec<&mut NoiseGraph> {
+        match self {
+            NoiseGraph::Simplex { .. }
+            | NoiseGraph::OctavedSimplex { .. }
+            | NoiseGraph::Perlin { .. }
+            | NoiseGraph::Value { .. }
+            | NoiseGraph::Worley { .. }
+            | NoiseGraph::Constant { .. } => vec![],
+            NoiseGraph::Fractal { source, .. }
+            | NoiseGraph::Derive { source, .. }
+            | NoiseGraph::ScalePoint { source, .. }
+            | NoiseGraph::Scale { source, .. }
+            | NoiseGraph::Offset { source, .. }
+            | NoiseGraph::Clamp { source, .. }
+            | NoiseGraph::Tiled { source, .. } => vec![source],
+            NoiseGraph::Select {
+                control, low, high, ..
+            } => vec![control, low, high],
+            NoiseGraph::Blend { a, b, control } => vec![a, b, control],
+            NoiseGraph::DomainWarp { source, warp, .. } => vec![source, warp],
+        }
+    }
+
+    /// Makes every `Tiled` node in the graph repeat after `tile_distance`.
+    pub fn set_tile_distance(&mut self, tile_distance: f64) {
+        if let NoiseGraph::Tiled {
+            tile_distance: own, ..
+        } = self
+        {
+            *own = tile_distance;
+        }
+        for input in self.inputs_mut() {
+            input.set_tile_distance(tile_distance);
+        }
+    }
+
+    pub fn from_ron(text: &str) -> Result<NoiseGraph, Box<dyn Error>> {
+        Ok(ron::de::from_str(text)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String, Box<dyn Error>> {
+        Ok(ron::ser::to_string_pretty(self, Default::default())?)
+    }
+
+    pub fn from_json(text: &str) -> Result<NoiseGraph, Box<dyn Error>> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Loads a graph from a `.json` file, or from RON for any other extension, and checks it
+    /// for points with `input` coordinates.
+    pub fn load<P: AsRef<Path>>(path: P, input: usize) -> Result<NoiseGraph, Box<dyn Error>> {
+        let text = fs::read_to_string(&path)?;
+        let graph = match path.as_ref().extension() {
+            Some(extension) if extension == "json" => NoiseGraph::from_json(&text)?,
+            _ => NoiseGraph::from_ron(&text)?,
+        };
+        graph.check(input)?;
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::procgen::noise::simplex_noise::TiledOctavedSimplexNoise;
+    use crate::procgen::procseed::ProcSeed;
+
+    fn mountains() -> NoiseGraph {
+        NoiseGraph::Tiled {
+            dim: 2,
+            tile_distance: 1.0,
+            scale: 0.5,
+            skewed: true,
+            source: Box::new(NoiseGraph::Select {
+                control: Box::new(NoiseGraph::Perlin { dim: 5 }),
+                low: Box::new(NoiseGraph::Scale {
+                    source: Box::new(NoiseGraph::Fractal {
+                        source: Box::new(NoiseGraph::Simplex { dim: 5 }),
+                        kind: FractalKind::Fbm,
+                        octaves: 4,
+                        frequency: 1.0,
+                        lacunarity: 2.0,
+                        gain: 0.5,
+                    }),
+                    factor: 0.3,
+                }),
+                high: Box::new(NoiseGraph::DomainWarp {
+                    source: Box::new(NoiseGraph::Fractal {
+                        source: Box::new(NoiseGraph::Simplex { dim: 5 }),
+                        kind: FractalKind::Ridged,
+                        octaves: 6,
+                        frequency: 2.0,
+                        lacunarity: 2.0,
+                        gain: 0.5,
+                    }),
+                    warp: Box::new(NoiseGraph::Worley {
+                        dim: 5,
+                        output: WorleyOutput::F1,
+                    }),
+                    strength: 0.1,
+                }),
+                threshold: 0.0,
+                falloff: 0.1,
+            }),
+        }
+    }
+
+    #[test]
+    fn graphs_round_trip_through_ron_and_json() {
+        let graph = mountains();
+        assert_eq!(
+            NoiseGraph::from_ron(&graph.to_ron().unwrap()).unwrap(),
+            graph
+        );
+        assert_eq!(
+            NoiseGraph::from_json(&graph.to_json().unwrap()).unwrap(),
+            graph
+        );
+        assert!(NoiseGraph::from_ron("Simplex(dims: 2)").is_err());
+    }
+
+    #[test]
+    fn written_graphs_build_the_noise_they_describe() {
+        let text = "
+            Tiled(
+                dim: 2,
+                tile_distance: 1.0,
+                scale: 0.5,
+                skewed: true,
+                source: OctavedSimplex(dim: 5, octaves: 4, octave_factor: 0.5),
+            )";
+        let mut graph = NoiseGraph::from_ron(text).unwrap();
+        let mut layer = TiledOctavedSimplexNoise::skewed_octaved(2, 4, 0.5, 1.0, 0.5);
+        let seed = ProcSeed::new(&"graph", 0.25);
+        let point = vec![0.3, 0.7];
+        assert_eq!(
+            graph.build(2).unwrap().get_noise(&seed, &point),
+            layer.get_noise(&seed, &point)
+        );
+
+        graph.set_tile_distance(2.0);
+        layer.tile_distance = 2.0;
+        assert_eq!(
+            graph.build(2).unwrap().get_noise(&seed, &point),
+            layer.get_noise(&seed, &point)
+        );
+
+        // fractal parameters have defaults
+        let fbm =
+            NoiseGraph::from_ron("Fractal(kind: Fbm, octaves: 3, source: Perlin(dim: 2))").unwrap();
+        match fbm {
+            NoiseGraph::Fractal {
+                frequency,
+                lacunarity,
+                gain,
+                ..
+            } => assert_eq!((frequency, lacunarity, gain), (1.0, 2.0, 0.5)),
+            _ => unreachable!(),
+        }
+        let value = mountains().build(2).unwrap().get_noise(&seed, &point);
+        assert!(value.is_finite());
+    }
+
+    #[test]
+    fn graphs_that_would_panic_or_give_nan_are_refused() {
+        assert!(mountains().check(2).is_ok());
+        // a plain torus has four axes, too few for five dimensional simplex noise
+        let graph = NoiseGraph::from_ron(
+            "Tiled(dim: 2, tile_distance: 1.0, scale: 0.5, source: Simplex(dim: 5))",
+        )
+        .unwrap();
+        assert_eq!(
+            graph.check(2),
+            Err(GraphError::Dimensions {
+                node: "Simplex",
+                dim: 5,
+                input: 4,
+            })
+        );
+        assert!(graph.build(2).is_err());
+        assert_eq!(
+            NoiseGraph::Simplex { dim: 3 }.check(2),
+            Err(GraphError::Dimensions {
+                node: "Simplex",
+                dim: 3,
+                input: 2,
+            })
+        );
+        assert_eq!(
+            NoiseGraph::Worley {
+                dim: 0,
+                output: WorleyOutput::F1,
+            }
+            .check(2),
+            Err(GraphError::Zero {
+                node: "Worley",
+                field: "dim",
+            })
+        );
+        let fractal = |octaves, gain| NoiseGraph::Fractal {
+            source: Box::new(NoiseGraph::Perlin { dim: 2 }),
+            kind: FractalKind::Fbm,
+            octaves: octaves,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            gain: gain,
+        };
+        assert_eq!(
+            fractal(0, 0.5).check(2),
+            Err(GraphError::Zero {
+                node: "Fractal",
+                field: "octaves",
+            })
+        );
+        assert_eq!(
+            fractal(3, f64::NAN).check(2),
+            Err(GraphError::NotFinite {
+                node: "Fractal",
+                field: "gain",
+            })
+        );
+    }
+}
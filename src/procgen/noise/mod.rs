@@ -1,10 +1,148 @@
+pub mod combinators;
+pub mod fractal;
+pub mod graph;
+pub mod perlin_noise;
 pub mod simplex_noise;
+pub mod value_noise;
+pub mod worley_noise;
 use crate::procgen::procseed::ProcSeed;
+use std::f64::consts::PI;
 
 pub trait Noise {
     fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64;
+
+    /// Noise at many points at once, given as one vector of coordinates per axis, so that
+    /// `axes[d][i]` is coordinate `d` of point `i`. Each value is exactly what `get_noise` gives
+    /// for that point; implementations only override this to do the same work faster.
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        let mut point = vec![0.0; axes.len()];
+        (0..batch_len(axes))
+            .map(|i| {
+                for (x, axis) in point.iter_mut().zip(axes) {
+                    *x = axis[i];
+                }
+                self.get_noise(seed, &point)
+            })
+            .collect()
+    }
+}
+
+impl<N: Noise + ?Sized> Noise for Box<N> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        (**self).get_noise(seed, point)
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        (**self).get_noise_batch(seed, axes)
+    }
 }
 
 pub trait NoiseDerivative {
     fn get_noise_derivative(&self, seed: &ProcSeed, point: &Vec<f64>) -> Vec<f64>;
 }
+
+/// Rearranges a list of points into one vector per axis, as `Noise::get_noise_batch` takes them.
+pub fn to_axes(points: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let dim = points.first().map_or(0, |p| p.len());
+    (0..dim)
+        .map(|d| points.iter().map(|p| p[d]).collect())
+        .collect()
+}
+
+/// Number of points in a batch, which every axis must agree on.
+pub fn batch_len(axes: &[Vec<f64>]) -> usize {
+    let len = axes.first().map_or(0, |a| a.len());
+    assert!(
+        axes.iter().all(|a| a.len() == len),
+        "every axis of a batch needs a coordinate for every point"
+    );
+    len
+}
+
+/// `count` scattered 2D points with coordinates from 0 to `scale`, for tests to sample noise at.
+/// The origin is left out, as every lattice noise has a vertex there.
+#[cfg(test)]
+fn test_points(count: usize, scale: f64) -> Vec<Vec<f64>> {
+    (1..=count)
+        .map(|k| {
+            vec![
+                (k as f64 * 0.754).fract() * scale,
+                (k as f64 * 0.569).fract() * scale,
+            ]
+        })
+        .collect()
+}
+
+/// Maps a seed's hash onto `0.0..=1.0`.
+fn unit_hash(seed: &ProcSeed) -> f64 {
+    seed.get() as f64 / u64::MAX as f64
+}
+
+/// Perlin's quintic smoothstep, which has zero first and second derivatives at 0 and 1 so that
+/// lattice noise has no visible creases along cell boundaries.
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// The lattice cell containing the first `dim` coordinates of `point`, and the position within
+/// the cell.
+fn lattice_cell(dim: usize, point: &[f64]) -> (Vec<i64>, Vec<f64>) {
+    let cell: Vec<i64> = point[..dim].iter().map(|x| x.floor() as i64).collect();
+    let frac = (0..dim).map(|i| point[i] - cell[i] as f64).collect();
+    (cell, frac)
+}
+
+/// Maps each input axis onto a circle of radius `scale` in its own pair of source axes,
+/// so that the source noise repeats every `tile_distance` along that input axis.
+fn torus_point(dim: usize, tile_distance: f64, scale: f64, point: &Vec<f64>) -> Vec<f64> {
+    (0..dim * 2)
+        .map(|i| {
+            scale
+                * if i % 2 == 0 {
+                    (*point.get(i / 2).unwrap() * 2.0 * PI / tile_distance).sin()
+                } else {
+                    (*point.get(i / 2).unwrap() * 2.0 * PI / tile_distance).cos()
+                }
+        })
+        .collect()
+}
+
+/// Applies the chain rule through `torus_point`, taking a gradient in source space back to
+/// the caller's input space.
+fn torus_derivative(
+    dim: usize,
+    tile_distance: f64,
+    scale: f64,
+    point: &Vec<f64>,
+    der_source: &Vec<f64>,
+) -> Vec<f64> {
+    let freq = 2.0 * PI / tile_distance;
+    (0..dim)
+        .map(|i| {
+            let angle = *point.get(i).unwrap() * freq;
+            scale
+                * freq
+                * (der_source.get(i * 2).unwrap() * angle.cos()
+                    - der_source.get(i * 2 + 1).unwrap() * angle.sin())
+        })
+        .collect()
+}
+
+/// `torus_point` for a batch of points given one vector per axis.
+fn torus_axes(dim: usize, tile_distance: f64, scale: f64, axes: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    (0..dim * 2)
+        .map(|i| {
+            axes[i / 2]
+                .iter()
+                .map(|x| {
+                    scale
+                        * if i % 2 == 0 {
+                            (*x * 2.0 * PI / tile_distance).sin()
+                        } else {
+                            (*x * 2.0 * PI / tile_distance).cos()
+                        }
+                })
+                .collect()
+        })
+        .collect()
+}
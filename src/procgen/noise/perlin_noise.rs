@@ -0,0 +1,123 @@
+use super::{fade, lattice_cell};
+use crate::procgen::procseed::ProcSeed;
+use std::f64::consts::PI;
+
+/// Classic gradient noise over the integer lattice: every corner of the cell containing a point
+/// gets a gradient picked by hashing the corner, and the point's offsets from the corners,
+/// dotted with their gradients, are blended with `fade`. It is zero at every lattice point.
+pub struct PerlinNoise {
+    pub dim: usize,
+    pub grads: Vec<Vec<f64>>,
+    pub value_scalar: f64,
+}
+
+impl PerlinNoise {
+    pub fn new(dim: usize) -> PerlinNoise {
+        // the midpoints of the edges of a hypercube, as in Perlin's improved noise, which have
+        // no bias towards the axes or the diagonals from three dimensions up. In two they would
+        // all lie along the axes, so eight directions spaced evenly around the circle are used.
+        let grads = if dim == 2 {
+            (0..8)
+                .map(|i| {
+                    let angle = i as f64 * PI / 4.0;
+                    vec![angle.cos(), angle.sin()]
+                })
+                .collect()
+        } else if dim > 1 {
+            let combinations = 1 << (dim - 1);
+            (0..dim * combinations)
+                .map(|i| {
+                    let zero = i / combinations;
+                    (0..dim)
+                        .map(|j| {
+                            if j == zero {
+                                0.0
+                            } else {
+                                let bit = if j < zero { j } else { j - 1 };
+                                (((i % combinations) >> bit) & 1) as f64 * 2.0 - 1.0
+                            }
+                        })
+                        .collect()
+                })
+                .collect()
+        } else {
+            vec![vec![-1.0], vec![1.0]]
+        };
+
+        PerlinNoise {
+            dim: dim,
+            grads: grads,
+            value_scalar: SCALARS[dim.min(SCALARS.len() - 1)],
+        }
+    }
+}
+
+/// Brings the noise to roughly `-1.0..1.0`, indexed by number of dimensions. One dimension is
+/// exact; the rest were measured by sampling, and higher dimensions use the last.
+const SCALARS: [f64; 6] = [1.0, 2.0, 1.41, 1.0, 0.9, 0.8];
+
+impl super::Noise for PerlinNoise {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        let (cell, frac) = lattice_cell(self.dim, point);
+        let faded: Vec<f64> = frac.iter().map(|t| fade(*t)).collect();
+        let mut corner = cell.clone();
+        let mut noise = 0.0;
+        for c in 0..(1 << self.dim) {
+            let mut weight = 1.0;
+            for i in 0..self.dim {
+                let bit = (c >> i) & 1;
+                corner[i] = cell[i] + bit;
+                weight *= if bit == 1 { faded[i] } else { 1.0 - faded[i] };
+            }
+            let hash = seed.derive(&corner).get();
+            let grad = &self.grads[(hash % self.grads.len() as u64) as usize];
+            let mut dotprod = 0.0;
+            for i in 0..self.dim {
+                dotprod += grad[i] * (frac[i] - (corner[i] - cell[i]) as f64);
+            }
+            noise += weight * dotprod;
+        }
+        noise * self.value_scalar
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{test_points, Noise};
+    use super::*;
+
+    #[test]
+    fn zero_on_the_lattice_and_continuous_across_cells() {
+        let seed = ProcSeed::new(&"perlin", 0.0);
+        for dim in 1..5 {
+            let noise = PerlinNoise::new(dim);
+            for k in -3..3 {
+                let lattice: Vec<f64> = (0..dim).map(|i| (k * (i as i64 + 1)) as f64).collect();
+                assert_eq!(noise.get_noise(&seed, &lattice), 0.0);
+            }
+            let mut below = vec![0.37; dim];
+            let mut above = below.clone();
+            below[0] = 1.0 - 1e-9;
+            above[0] = 1.0 + 1e-9;
+            let gap = noise.get_noise(&seed, &below) - noise.get_noise(&seed, &above);
+            assert!(gap.abs() < 1e-6, "{} dimensions", dim);
+        }
+    }
+
+    #[test]
+    fn roughly_unit_range() {
+        let seed = ProcSeed::new(&"perlin", 0.0);
+        let other = ProcSeed::new(&"other", 0.0);
+        let noise = PerlinNoise::new(2);
+        let mut max: f64 = 0.0;
+        let mut differs = false;
+        for point in test_points(2000, 20.0) {
+            let value = noise.get_noise(&seed, &point);
+            assert!(value.abs() <= 1.0, "{} at {:?}", value, point);
+            max = max.max(value.abs());
+            differs |= value != noise.get_noise(&other, &point);
+        }
+        assert!(max > 0.5);
+        assert!(differs);
+    }
+}
@@ -1,28 +1,60 @@
-use crate::procgen::procseed::ProcSeed;
+use super::batch_len;
+use super::combinators::Tiled;
+use super::fractal::{Fractal, FractalKind};
+use crate::procgen::procseed::{HashVersion, ProcSeed};
 use std::cmp::Ordering;
-use std::f64::consts::PI;
 
-pub struct SimplexNoise {
-    pub dim: usize,
+/// The lattice and the falloff around each vertex of a simplex. Points are skewed by `skew`
+/// times the sum of their coordinates to find their simplex, and displacements unskewed by
+/// `unskew` per step. A vertex at displacement `u` from the point contributes
+/// `(corner_to_face_sq - |u|^2)^power (g . u)` for its gradient `g`, and the sum is multiplied
+/// by `value_scalar`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimplexKernel {
     pub skew: f64,
     pub unskew: f64,
-    pub side_len: f64,
     pub corner_to_face_sq: f64,
-    pub grads: Vec<Vec<f64>>,
+    pub power: i32,
     pub value_scalar: f64,
 }
 
-impl SimplexNoise {
-    pub fn new(dim: usize) -> SimplexNoise {
-        let skew = if dim > 1 {
-            ((dim as f64 + 1.0).sqrt() - 1.0) / dim as f64
-        } else {
-            0.0
-        };
-        let unskew = if dim > 1 {
-            skew / (dim as f64 * skew + 1.0)
+/// `SimplexKernel::value_scalar` by dimension, found by sampling. From two dimensions up the
+/// noise spreads as far as it did with the `V1` kernel, so that terrain tuned on it keeps its
+/// balance of land and sea; as with that kernel, peaks stray past 1, up to about 1.6. In one
+/// dimension, where `V1` was broken, the peaks reach 1. Higher dimensions use the last.
+const VALUE_SCALARS: [f64; 8] = [1.0, 10.06, 16.31, 11.69, 9.498, 9.762, 9.835, 9.535];
+
+impl SimplexKernel {
+    /// The kernel for seeds of `version`.
+    pub fn new(dim: usize, version: HashVersion) -> SimplexKernel {
+        match version {
+            HashVersion::V1 => SimplexKernel::legacy(dim),
+            HashVersion::V2 => SimplexKernel {
+                skew: skew(dim),
+                unskew: skew(dim) / (dim as f64 * skew(dim) + 1.0),
+                // every vertex of a simplex is at least this far from the face opposite it, so
+                // a vertex stops contributing before the point leaves its simplices
+                corner_to_face_sq: 0.5,
+                // the fourth power gets so peaky within that radius above three dimensions
+                // that most of the map flattens out between isolated bumps; the square keeps
+                // the noise and its derivative continuous and spreads it like `V1`
+                power: 2,
+                value_scalar: VALUE_SCALARS[dim.min(VALUE_SCALARS.len() - 1)],
+            },
+        }
+    }
+
+    /// The kernel seeds of `HashVersion::V1` keep. Above two dimensions its radius is larger
+    /// than the distance from a vertex to the opposite face, so vertices still contribute
+    /// when the point crosses into a simplex they are not part of, and the noise jumps along
+    /// the straight boundaries between simplices. In one dimension the lattice is not skewed
+    /// and displacements are unskewed by a whole step, so both vertices of a segment sit at
+    /// the point's own coordinate and the noise is zero beyond one step from the origin.
+    fn legacy(dim: usize) -> SimplexKernel {
+        let (skew, unskew) = if dim > 1 {
+            (skew(dim), skew(dim) / (dim as f64 * skew(dim) + 1.0))
         } else {
-            1.0
+            (0.0, 1.0)
         };
         let side_len = (dim as f64).sqrt() / (dim as f64 * skew + 1.0);
         let a = (side_len.powi(2) - (side_len / 2.0).powi(2)).sqrt();
@@ -39,7 +71,44 @@ impl SimplexNoise {
         } else {
             1.0
         };
+        SimplexKernel {
+            skew: skew,
+            unskew: unskew,
+            corner_to_face_sq: corner_to_face.powi(2),
+            power: 4,
+            value_scalar: value_scalar,
+        }
+    }
+}
 
+/// The factor that skews the simplex lattice in `dim` dimensions onto the hypercube lattice.
+fn skew(dim: usize) -> f64 {
+    ((dim as f64 + 1.0).sqrt() - 1.0) / dim as f64
+}
+
+/// Picks one of `count` gradients for a vertex from its hash.
+fn gradient_index(version: HashVersion, hash: u64, count: usize) -> usize {
+    match version {
+        // the hash as a fraction rounds up to 1 for the largest hashes, which would pick a
+        // gradient past the end
+        HashVersion::V1 => {
+            let hash_fract = hash as f64 / u64::MAX as f64;
+            ((hash_fract * count as f64).floor() as usize).min(count - 1)
+        }
+        // the high word of the product, which splits the hashes into `count` even ranges
+        HashVersion::V2 => ((hash as u128 * count as u128) >> 64) as usize,
+    }
+}
+
+pub struct SimplexNoise {
+    pub dim: usize,
+    pub grads: Vec<Vec<f64>>,
+    pub kernel: SimplexKernel,
+    pub legacy_kernel: SimplexKernel,
+}
+
+impl SimplexNoise {
+    pub fn new(dim: usize) -> SimplexNoise {
         let combinations: Vec<Vec<f64>> = (0..(2 as i32).pow((dim - 1) as u32))
             .map(|i| {
                 (0..(dim - 1))
@@ -74,22 +143,65 @@ impl SimplexNoise {
 
         SimplexNoise {
             dim: dim,
-            skew: skew,
-            unskew: unskew,
-            side_len: side_len,
-            corner_to_face_sq: corner_to_face.powi(2),
             grads: grads,
-            value_scalar: value_scalar,
+            kernel: SimplexKernel::new(dim, HashVersion::CURRENT),
+            legacy_kernel: SimplexKernel::new(dim, HashVersion::V1),
+        }
+    }
+
+    /// The kernel for `seed`'s version.
+    pub fn get_kernel(&self, seed: &ProcSeed) -> &SimplexKernel {
+        match seed.get_version() {
+            HashVersion::V1 => &self.legacy_kernel,
+            HashVersion::V2 => &self.kernel,
         }
     }
 
     fn get(&self, seed: &ProcSeed, point: &Vec<f64>) -> (f64, Vec<f64>) {
+        match self.dim {
+            2 => self.get_fixed::<2>(seed, point),
+            3 => self.get_fixed::<3>(seed, point),
+            4 => self.get_fixed::<4>(seed, point),
+            5 => self.get_fixed::<5>(seed, point),
+            _ => self.get_generic(seed, point),
+        }
+    }
+
+    /// The noise alone, without working out the derivative where `FixedSimplexNoise` can skip
+    /// it.
+    fn get_value(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        match self.dim {
+            2 => self.fixed::<2>().get_value(seed, &fixed_point(point)),
+            3 => self.fixed::<3>().get_value(seed, &fixed_point(point)),
+            4 => self.fixed::<4>().get_value(seed, &fixed_point(point)),
+            5 => self.fixed::<5>().get_value(seed, &fixed_point(point)),
+            _ => self.get_generic(seed, point).0,
+        }
+    }
+
+    fn fixed<const N: usize>(&self) -> FixedSimplexNoise<N> {
+        FixedSimplexNoise {
+            kernel: self.kernel,
+            legacy_kernel: self.legacy_kernel,
+        }
+    }
+
+    fn get_fixed<const N: usize>(&self, seed: &ProcSeed, point: &[f64]) -> (f64, Vec<f64>) {
+        let (noise, der) = self.fixed::<N>().get(seed, &fixed_point(point));
+        (noise, der.to_vec())
+    }
+
+    /// The reference implementation, for any number of dimensions. It allocates several vectors
+    /// per sample, so `get` only falls back to it outside the dimensions `FixedSimplexNoise`
+    /// covers.
+    pub fn get_generic(&self, seed: &ProcSeed, point: &Vec<f64>) -> (f64, Vec<f64>) {
+        let kernel = self.get_kernel(seed);
         // skew factor
         let mut s = 0.0;
         for x in point.iter() {
             s += *x;
         }
-        s *= self.skew;
+        s *= kernel.skew;
 
         // skew input point
         let skewed_int_point: Vec<i64> = (0..self.dim)
@@ -101,7 +213,7 @@ impl SimplexNoise {
         for x in skewed_int_point.iter() {
             t += *x as f64;
         }
-        t *= self.unskew;
+        t *= kernel.unskew;
         //println!("t: {}", t);
 
         // unskew displacement from hypercube origin
@@ -135,34 +247,35 @@ impl SimplexNoise {
                         + unskew_total
                 })
                 .collect();
-            let mut attenuation = self.corner_to_face_sq;
+            let mut attenuation = kernel.corner_to_face_sq;
             for x in u.iter() {
                 attenuation -= x.powi(2);
             }
             if attenuation > 0.0 {
                 let vseed = seed.derive(&vertex);
                 let hash = vseed.get();
-                let hash_fract = (hash as u64) as f64 / u64::max_value() as f64;
-                let hash_index = (hash_fract * self.grads.len() as f64).floor() as usize;
+                let hash_index = gradient_index(seed.get_version(), hash, self.grads.len());
                 let grad = self.grads.get(hash_index).unwrap();
 
                 let mut dotprod = 0.0;
                 for j in 0..self.dim {
                     dotprod += grad.get(j).unwrap() * u.get(j).unwrap();
                 }
-                noise += dotprod * attenuation.powi(4);
+                let falloff = attenuation.powi(kernel.power);
+                let slope = 2.0 * kernel.power as f64 * attenuation.powi(kernel.power - 1);
+                noise += dotprod * falloff;
                 der = (0..self.dim)
                     .map(|i| {
-                        der.get(i).unwrap() + grad.get(i).unwrap() * attenuation.powi(4)
-                            - (dotprod * 8.0 * attenuation.powi(3)) * u.get(i).unwrap()
+                        der.get(i).unwrap() + grad.get(i).unwrap() * falloff
+                            - (dotprod * slope) * u.get(i).unwrap()
                     })
                     .collect();
             }
-            unskew_total += self.unskew;
+            unskew_total += kernel.unskew;
         }
-        noise *= self.value_scalar;
+        noise *= kernel.value_scalar;
         der = (0..self.dim)
-            .map(|i| der.get(i).unwrap() * self.value_scalar)
+            .map(|i| der.get(i).unwrap() * kernel.value_scalar)
             .collect();
         return (noise, der);
     }
@@ -170,127 +283,829 @@ impl SimplexNoise {
 
 impl super::Noise for SimplexNoise {
     fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
-        let (noise, _) = self.get(seed, &point.clone());
-        return noise;
+        self.get_value(seed, point)
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        match self.dim {
+            2 => self.fixed::<2>().get_noise_batch(seed, axes),
+            3 => self.fixed::<3>().get_noise_batch(seed, axes),
+            4 => self.fixed::<4>().get_noise_batch(seed, axes),
+            5 => self.fixed::<5>().get_noise_batch(seed, axes),
+            _ => (0..batch_len(axes))
+                .map(|i| {
+                    let point = axes.iter().map(|axis| axis[i]).collect();
+                    self.get_generic(seed, &point).0
+                })
+                .collect(),
+        }
+    }
+}
+
+impl super::NoiseDerivative for SimplexNoise {
+    fn get_noise_derivative(&self, seed: &ProcSeed, point: &Vec<f64>) -> Vec<f64> {
+        let (_, der) = self.get(seed, point);
+        return der;
+    }
+}
+
+/// The first `N` coordinates of `point`, as an array.
+fn fixed_point<const N: usize>(point: &[f64]) -> [f64; N] {
+    let mut p = [0.0; N];
+    p.copy_from_slice(&point[..N]);
+    p
+}
+
+/// Number of points `FixedSimplexNoise::get_lanes` evaluates together, enough to fill the widest
+/// common vector registers with `f64`s.
+pub const LANES: usize = 4;
+
+/// Simplex noise in `N` dimensions, for 2 to 5, that works on arrays and never touches the heap.
+/// It gives bit-for-bit the same values and derivatives as `SimplexNoise::get_generic`: every
+/// sum is accumulated in the same order, ties between axes are broken the same way and vertex
+/// seeds are derived from arrays, which hash exactly like the vectors used there.
+pub struct FixedSimplexNoise<const N: usize> {
+    pub kernel: SimplexKernel,
+    pub legacy_kernel: SimplexKernel,
+}
+
+impl<const N: usize> FixedSimplexNoise<N> {
+    pub fn new() -> FixedSimplexNoise<N> {
+        assert!(
+            (2..=5).contains(&N),
+            "fixed simplex noise is only implemented for 2 to 5 dimensions, not {}",
+            N
+        );
+        let generic = SimplexNoise::new(N);
+        FixedSimplexNoise {
+            kernel: generic.kernel,
+            legacy_kernel: generic.legacy_kernel,
+        }
+    }
+
+    /// The kernel for `seed`'s version.
+    pub fn get_kernel(&self, seed: &ProcSeed) -> &SimplexKernel {
+        match seed.get_version() {
+            HashVersion::V1 => &self.legacy_kernel,
+            HashVersion::V2 => &self.kernel,
+        }
+    }
+
+    /// Gradient `index` of the `N * 2^(N - 1)` in `SimplexNoise::grads`: the gradient has a zero
+    /// on axis `index / 2^(N - 1)` and its other coordinates are the bits of the remainder,
+    /// mapped to -1 and 1.
+    fn gradient(index: usize) -> [f64; N] {
+        let combinations = 1 << (N - 1);
+        let zero = index / combinations;
+        let bits = index % combinations;
+        let mut grad = [0.0; N];
+        for (j, g) in grad.iter_mut().enumerate() {
+            if j != zero {
+                let bit = if j < zero { j } else { j - 1 };
+                *g = ((bits >> bit) & 1) as f64 * 2.0 - 1.0;
+            }
+        }
+        grad
+    }
+
+    pub fn get(&self, seed: &ProcSeed, point: &[f64; N]) -> (f64, [f64; N]) {
+        self.sample::<true>(seed, point)
+    }
+
+    /// The noise at `point` without its derivative, which saves a little work per vertex.
+    pub fn get_value(&self, seed: &ProcSeed, point: &[f64; N]) -> f64 {
+        self.sample::<false>(seed, point).0
+    }
+
+    /// The noise at `point`, and its derivative if `DER` is set; otherwise the derivative is
+    /// left at zero.
+    fn sample<const DER: bool>(&self, seed: &ProcSeed, point: &[f64; N]) -> (f64, [f64; N]) {
+        let kernel = self.get_kernel(seed);
+        // skew factor
+        let mut s = 0.0;
+        for x in point.iter() {
+            s += *x;
+        }
+        s *= kernel.skew;
+
+        // skew input point
+        let mut skewed_int_point = [0i64; N];
+        for i in 0..N {
+            skewed_int_point[i] = (point[i] + s).floor() as i64;
+        }
+
+        // unskew factor
+        let mut t = 0.0;
+        for x in skewed_int_point.iter() {
+            t += *x as f64;
+        }
+        t *= kernel.unskew;
+
+        // unskew displacement from hypercube origin
+        let mut unskewed_displacement = [0.0; N];
+        for i in 0..N {
+            unskewed_displacement[i] = point[i] - skewed_int_point[i] as f64 + t;
+        }
+
+        // sort axes in descending order of displacement from hypercube origin, keeping tied
+        // axes in order as the stable sort in `get_generic` does
+        let mut axis_order = [0usize; N];
+        for i in 0..N {
+            let mut j = i;
+            while j > 0 && unskewed_displacement[i] > unskewed_displacement[axis_order[j - 1]] {
+                axis_order[j] = axis_order[j - 1];
+                j -= 1;
+            }
+            axis_order[j] = i;
+        }
+
+        // iterate through each vertex in the simplex, applying contribution
+        let grads = N << (N - 1);
+        let mut noise = 0.0;
+        let mut der = [0.0; N];
+        let mut unskew_total = 0.0;
+        let mut vertex = skewed_int_point;
+        for i in 0..(N + 1) {
+            if i != 0 {
+                vertex[axis_order[i - 1]] += 1;
+            }
+            let mut u = [0.0; N];
+            for j in 0..N {
+                u[j] = unskewed_displacement[j] - (vertex[j] - skewed_int_point[j]) as f64
+                    + unskew_total;
+            }
+            let mut attenuation = kernel.corner_to_face_sq;
+            for x in u.iter() {
+                attenuation -= x.powi(2);
+            }
+            if attenuation > 0.0 {
+                let vseed = seed.derive(&vertex);
+                let hash = vseed.get();
+                let hash_index = gradient_index(seed.get_version(), hash, grads);
+                let grad = Self::gradient(hash_index);
+
+                let mut dotprod = 0.0;
+                for j in 0..N {
+                    dotprod += grad[j] * u[j];
+                }
+                let falloff = attenuation.powi(kernel.power);
+                noise += dotprod * falloff;
+                if DER {
+                    let slope = 2.0 * kernel.power as f64 * attenuation.powi(kernel.power - 1);
+                    for j in 0..N {
+                        der[j] = der[j] + grad[j] * falloff - (dotprod * slope) * u[j];
+                    }
+                }
+            }
+            unskew_total += kernel.unskew;
+        }
+        noise *= kernel.value_scalar;
+        if DER {
+            for d in der.iter_mut() {
+                *d *= kernel.value_scalar;
+            }
+        }
+        (noise, der)
+    }
+
+    /// The noise at `LANES` points at once, given as one array of coordinates per axis. Each
+    /// step of `get` is done for every lane before the next, in loops the compiler can turn into
+    /// SIMD instructions, and only hashing the vertices that contribute is done lane by lane.
+    /// The values are bit-for-bit those of `get`.
+    pub fn get_lanes(&self, seed: &ProcSeed, point: &[[f64; LANES]; N]) -> [f64; LANES] {
+        let kernel = self.get_kernel(seed);
+        // skew factor
+        let mut s = [0.0; LANES];
+        for axis in point.iter() {
+            for l in 0..LANES {
+                s[l] += axis[l];
+            }
+        }
+        for x in s.iter_mut() {
+            *x *= kernel.skew;
+        }
+
+        // skew input point
+        let mut skewed_int_point = [[0i64; LANES]; N];
+        for i in 0..N {
+            for l in 0..LANES {
+                skewed_int_point[i][l] = (point[i][l] + s[l]).floor() as i64;
+            }
+        }
+
+        // unskew factor
+        let mut t = [0.0; LANES];
+        for axis in skewed_int_point.iter() {
+            for l in 0..LANES {
+                t[l] += axis[l] as f64;
+            }
+        }
+        for x in t.iter_mut() {
+            *x *= kernel.unskew;
+        }
+
+        // unskew displacement from hypercube origin
+        let mut unskewed_displacement = [[0.0; LANES]; N];
+        for i in 0..N {
+            for l in 0..LANES {
+                unskewed_displacement[i][l] = point[i][l] - skewed_int_point[i][l] as f64 + t[l];
+            }
+        }
+
+        // instead of sorting the axes, find the position of each in the order `get` sorts
+        // them into: the number of axes with a larger displacement, or an equal one and a
+        // lower index. The vertex after `i` steps has moved along the axes ranked below `i`.
+        let mut rank = [[0usize; LANES]; N];
+        for i in 0..N {
+            for k in 0..N {
+                for l in 0..LANES {
+                    let d = &unskewed_displacement;
+                    let before = if k < i {
+                        d[k][l] >= d[i][l]
+                    } else {
+                        d[k][l] > d[i][l]
+                    };
+                    rank[i][l] += before as usize;
+                }
+            }
+        }
+
+        // iterate through each vertex in the simplex, applying contribution
+        let grads = N << (N - 1);
+        let mut noise = [0.0; LANES];
+        let mut unskew_total = 0.0;
+        for i in 0..(N + 1) {
+            let mut u = [[0.0; LANES]; N];
+            for j in 0..N {
+                for l in 0..LANES {
+                    let step = (rank[j][l] < i) as i64;
+                    u[j][l] = unskewed_displacement[j][l] - step as f64 + unskew_total;
+                }
+            }
+            let mut attenuation = [kernel.corner_to_face_sq; LANES];
+            for axis in u.iter() {
+                for l in 0..LANES {
+                    attenuation[l] -= axis[l].powi(2);
+                }
+            }
+            for l in 0..LANES {
+                if attenuation[l] > 0.0 {
+                    let mut vertex = [0i64; N];
+                    for j in 0..N {
+                        vertex[j] = skewed_int_point[j][l] + (rank[j][l] < i) as i64;
+                    }
+                    let hash = seed.derive(&vertex).get();
+                    let hash_index = gradient_index(seed.get_version(), hash, grads);
+                    let grad = Self::gradient(hash_index);
+
+                    let mut dotprod = 0.0;
+                    for j in 0..N {
+                        dotprod += grad[j] * u[j][l];
+                    }
+                    noise[l] += dotprod * attenuation[l].powi(kernel.power);
+                }
+            }
+            unskew_total += kernel.unskew;
+        }
+        for x in noise.iter_mut() {
+            *x *= kernel.value_scalar;
+        }
+        noise
+    }
+}
+
+impl<const N: usize> Default for FixedSimplexNoise<N> {
+    fn default() -> FixedSimplexNoise<N> {
+        FixedSimplexNoise::new()
+    }
+}
+
+impl<const N: usize> super::Noise for FixedSimplexNoise<N> {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        self.get_value(seed, &fixed_point(point))
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        let len = batch_len(axes);
+        let mut noise = Vec::with_capacity(len);
+        let mut point = [[0.0; LANES]; N];
+        for start in (0..len).step_by(LANES) {
+            // lanes past the end of the batch keep stale coordinates and are thrown away
+            let count = LANES.min(len - start);
+            for (lanes, axis) in point.iter_mut().zip(axes) {
+                lanes[..count].copy_from_slice(&axis[start..start + count]);
+            }
+            noise.extend_from_slice(&self.get_lanes(seed, &point)[..count]);
+        }
+        noise
     }
 }
 
+impl<const N: usize> super::NoiseDerivative for FixedSimplexNoise<N> {
+    fn get_noise_derivative(&self, seed: &ProcSeed, point: &Vec<f64>) -> Vec<f64> {
+        let (_, der) = self.get(seed, &fixed_point(point));
+        der.to_vec()
+    }
+}
+
+/// Octaves of simplex noise summed as fBm: octave `i` is sampled at `octave_factor^-i` times
+/// the frequency of the first and weighted by `octave_factor^i`.
 pub struct OctavedSimplexNoise {
     pub dim: usize,
-    pub octaves: Vec<SimplexNoise>,
-    pub octave_factor: f64,
+    pub fractal: Fractal<SimplexNoise>,
 }
 
 impl OctavedSimplexNoise {
     pub fn new(dim: usize, num_octaves: usize, octave_factor: f64) -> OctavedSimplexNoise {
-        let octaves = (0..num_octaves).map(|_| SimplexNoise::new(dim)).collect();
-
         OctavedSimplexNoise {
             dim: dim,
-            octaves: octaves,
-            octave_factor: octave_factor,
+            fractal: Fractal {
+                source: SimplexNoise::new(dim),
+                kind: FractalKind::Fbm,
+                octaves: num_octaves,
+                frequency: 1.0,
+                lacunarity: 1.0 / octave_factor,
+                gain: octave_factor,
+            },
+        }
+    }
+
+    fn get(&self, seed: &ProcSeed, point: &Vec<f64>) -> (f64, Vec<f64>) {
+        match self.dim {
+            2 => self.get_fixed_with_derivative::<2>(seed, point),
+            3 => self.get_fixed_with_derivative::<3>(seed, point),
+            4 => self.get_fixed_with_derivative::<4>(seed, point),
+            5 => self.get_fixed_with_derivative::<5>(seed, point),
+            _ => self.get_generic(seed, point),
+        }
+    }
+
+    fn get_value(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        match self.dim {
+            2 => self.get_fixed::<2, false>(seed, point).0,
+            3 => self.get_fixed::<3, false>(seed, point).0,
+            4 => self.get_fixed::<4, false>(seed, point).0,
+            5 => self.get_fixed::<5, false>(seed, point).0,
+            _ => self.get_generic(seed, point).0,
+        }
+    }
+
+    fn get_fixed_with_derivative<const N: usize>(
+        &self,
+        seed: &ProcSeed,
+        point: &[f64],
+    ) -> (f64, Vec<f64>) {
+        let (noise, der) = self.get_fixed::<N, true>(seed, point);
+        (noise, der.to_vec())
+    }
+
+    /// `get_generic` on arrays, through `FixedSimplexNoise`, working out the derivative only if
+    /// `DER` is set. The values are bit-for-bit the same.
+    fn get_fixed<const N: usize, const DER: bool>(
+        &self,
+        seed: &ProcSeed,
+        point: &[f64],
+    ) -> (f64, [f64; N]) {
+        let octave = self.fractal.source.fixed::<N>();
+        let mut noise = 0.0;
+        let mut der = [0.0; N];
+        for (i, (frequency, amplitude)) in self.fractal.steps().enumerate() {
+            let mut octave_point = [0.0; N];
+            for j in 0..N {
+                octave_point[j] = point[j] * frequency;
+            }
+            let (oct_noise, oct_der) = octave.sample::<DER>(&seed.derive(&i), &octave_point);
+            noise += oct_noise * amplitude;
+            if DER {
+                for j in 0..N {
+                    der[j] += oct_der[j] * frequency * amplitude;
+                }
+            }
+        }
+        let total = self.fractal.total_amplitude();
+        for d in der.iter_mut() {
+            *d /= total;
         }
+        (noise / total, der)
+    }
+
+    /// The reference implementation, for any number of dimensions.
+    fn get_generic(&self, seed: &ProcSeed, point: &Vec<f64>) -> (f64, Vec<f64>) {
+        let mut noise = 0.0;
+        let mut der: Vec<f64> = (0..self.dim).map(|_| 0.0).collect();
+        for (i, (frequency, amplitude)) in self.fractal.steps().enumerate() {
+            let (oct_noise, oct_der) = self.fractal.source.get(
+                &seed.derive(&i),
+                &(0..self.dim).map(|i| point[i] * frequency).collect(),
+            );
+            noise += oct_noise * amplitude;
+            // the octave is sampled at point * frequency and scaled by amplitude, so by the
+            // chain rule its gradient is scaled by both
+            for j in 0..self.dim {
+                der[j] += oct_der[j] * frequency * amplitude;
+            }
+        }
+        let total = self.fractal.total_amplitude();
+        return (
+            noise / total,
+            (0..self.dim).map(|i| der.get(i).unwrap() / total).collect(),
+        );
     }
 }
 
 impl super::Noise for OctavedSimplexNoise {
     fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
-        let noise: f64 = (0..self.octaves.len())
-            .map(|i| {
-                let mul = self.octave_factor.powi(i as i32);
-                let (oct_noise, _) = self.octaves.get(i).unwrap().get(
-                    &seed.derive(&i),
-                    &(0..self.dim).map(|i| point.get(i).unwrap() / mul).collect(),
-                );
-                let noise = oct_noise * mul;
-                noise
-            })
-            .sum();
-        //return (noise / scale, Vec::from_fn(self.dim, |i| *der.get(i) / scale));
-        let scale: f64 = (0..self.octaves.len())
-            .map(|i| self.octave_factor.powi(i as i32))
-            .sum();
-        return noise / scale;
+        self.get_value(seed, point)
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        self.fractal.get_noise_batch(seed, &axes[..self.dim])
     }
 }
 
-pub struct TiledOctavedSimplexNoise {
-    pub dim: usize,
-    pub tile_distance: f64,
-    pub scale: f64,
-    pub source: OctavedSimplexNoise,
+impl super::NoiseDerivative for OctavedSimplexNoise {
+    fn get_noise_derivative(&self, seed: &ProcSeed, point: &Vec<f64>) -> Vec<f64> {
+        let (_, der) = self.get(seed, point);
+        return der;
+    }
 }
 
+/// Octaved simplex noise that repeats along each input axis.
+pub type TiledOctavedSimplexNoise = Tiled<OctavedSimplexNoise>;
+
 impl TiledOctavedSimplexNoise {
-    pub fn new(
+    /// Octaves of simplex noise in `2 * dim` dimensions, tiled along `dim` input axes.
+    pub fn octaved(
         dim: usize,
         num_octaves: usize,
         octave_factor: f64,
         tile_distance: f64,
         scale: f64,
     ) -> TiledOctavedSimplexNoise {
-        TiledOctavedSimplexNoise {
+        Tiled {
+            source: OctavedSimplexNoise::new(dim * 2, num_octaves, octave_factor),
             dim: dim,
             tile_distance: tile_distance,
             scale: scale,
-            source: OctavedSimplexNoise::new(dim * 2, num_octaves, octave_factor),
+            skewed: false,
         }
     }
-}
-
-impl super::Noise for TiledOctavedSimplexNoise {
-    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
-        let point_source = (0..self.dim * 2)
-            .map(|i| {
-                self.scale
-                    * if i % 2 == 0 {
-                        (*point.get(i / 2).unwrap() * 2.0 * PI / self.tile_distance).sin()
-                    } else {
-                        (*point.get(i / 2).unwrap() * 2.0 * PI / self.tile_distance).cos()
-                    }
-            })
-            .collect();
-        return self.source.get_noise(seed, &point_source);
-    }
-}
 
-pub struct SkewedTiledOctavedSimplexNoise {
-    dim: usize,
-    tile_distance: f64,
-    scale: f64,
-    source: OctavedSimplexNoise,
-}
-
-impl SkewedTiledOctavedSimplexNoise {
-    pub fn new(
+    /// As `octaved`, with the seed's skew as one more axis of the source, which is the noise
+    /// each terrain layer starts from.
+    pub fn skewed_octaved(
         dim: usize,
         num_octaves: usize,
         octave_factor: f64,
         tile_distance: f64,
         scale: f64,
-    ) -> SkewedTiledOctavedSimplexNoise {
-        SkewedTiledOctavedSimplexNoise {
+    ) -> TiledOctavedSimplexNoise {
+        Tiled {
+            source: OctavedSimplexNoise::new(dim * 2 + 1, num_octaves, octave_factor),
             dim: dim,
             tile_distance: tile_distance,
             scale: scale,
-            source: OctavedSimplexNoise::new(dim * 2 + 1, num_octaves, octave_factor),
+            skewed: true,
         }
     }
 }
 
-impl super::Noise for SkewedTiledOctavedSimplexNoise {
-    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
-        let mut point_source: Vec<f64> = (0..self.dim * 2)
-            .map(|i| {
-                self.scale
-                    * if i % 2 == 0 {
-                        (*point.get(i / 2).unwrap() * 2.0 * PI / self.tile_distance).sin()
+#[cfg(test)]
+mod test {
+    use super::super::{Noise, NoiseDerivative};
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn assert_derivative_matches<N: Noise + NoiseDerivative>(noise: &N, dim: usize) {
+        let seed = ProcSeed::new(&"derivative", 0.25);
+        let h = 1e-6;
+        for k in 0..20 {
+            let point: Vec<f64> = (0..dim)
+                .map(|i| (0.05 + k as f64 * 0.137 + i as f64 * 0.291).fract())
+                .collect();
+            let der = noise.get_noise_derivative(&seed, &point);
+            assert_eq!(der.len(), dim);
+            for i in 0..dim {
+                let mut above = point.clone();
+                let mut below = point.clone();
+                above[i] += h;
+                below[i] -= h;
+                let approx =
+                    (noise.get_noise(&seed, &above) - noise.get_noise(&seed, &below)) / (2.0 * h);
+                assert!(
+                    (approx - der[i]).abs() < 1e-4 * (1.0 + approx.abs()),
+                    "axis {} at {:?}: analytic {} vs finite difference {}",
+                    i,
+                    point,
+                    der[i],
+                    approx
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn simplex_derivative() {
+        for dim in 2..6 {
+            assert_derivative_matches(&SimplexNoise::new(dim), dim);
+        }
+    }
+
+    fn assert_fixed_matches_generic<const N: usize>(version: HashVersion) {
+        let generic = SimplexNoise::new(N);
+        let fixed = FixedSimplexNoise::<N>::new();
+        let seed = ProcSeed::with_version(&"fixed", 0.0, version);
+        for k in 0..500 {
+            // lattice points and repeated coordinates exercise ties between axes
+            let point: Vec<f64> = (0..N)
+                .map(|i| {
+                    if k % 5 == 0 {
+                        ((k + i) % 3) as f64 - 1.0
                     } else {
-                        (*point.get(i / 2).unwrap() * 2.0 * PI / self.tile_distance).cos()
+                        ((k as f64 * 0.737 + i as f64 * 0.291).fract() - 0.5) * 20.0
                     }
+                })
+                .collect();
+            let mut array = [0.0; N];
+            array.copy_from_slice(&point);
+            let (noise, der) = generic.get_generic(&seed, &point);
+            let (fixed_noise, fixed_der) = fixed.get(&seed, &array);
+            assert_eq!(noise.to_bits(), fixed_noise.to_bits(), "{:?}", point);
+            for i in 0..N {
+                assert_eq!(der[i].to_bits(), fixed_der[i].to_bits(), "{:?}", point);
+            }
+            assert_eq!(generic.get_noise(&seed, &point).to_bits(), noise.to_bits());
+        }
+    }
+
+    #[test]
+    fn fixed_matches_generic() {
+        for version in [HashVersion::V1, HashVersion::V2].iter() {
+            assert_fixed_matches_generic::<2>(*version);
+            assert_fixed_matches_generic::<3>(*version);
+            assert_fixed_matches_generic::<4>(*version);
+            assert_fixed_matches_generic::<5>(*version);
+        }
+        assert_derivative_matches(&FixedSimplexNoise::<3>::new(), 3);
+    }
+
+    fn assert_batch_matches<N: Noise>(noise: &N, dim: usize) {
+        for version in [HashVersion::V1, HashVersion::V2].iter() {
+            assert_batch_matches_version(
+                noise,
+                dim,
+                ProcSeed::with_version(&"batch", 0.5, *version),
+            );
+        }
+    }
+
+    fn assert_batch_matches_version<N: Noise>(noise: &N, dim: usize, seed: ProcSeed) {
+        let points: Vec<Vec<f64>> = (0..23)
+            .map(|k| {
+                (0..dim)
+                    .map(|i| ((k as f64 * 0.737 + i as f64 * 0.291).fract() - 0.5) * 8.0)
+                    .collect()
             })
             .collect();
-        point_source.push(seed.get_skew());
-        return self.source.get_noise(seed, &point_source);
+        let batch = noise.get_noise_batch(&seed, &super::super::to_axes(&points));
+        assert_eq!(batch.len(), points.len());
+        for (point, value) in points.iter().zip(batch) {
+            assert_eq!(
+                value.to_bits(),
+                noise.get_noise(&seed, point).to_bits(),
+                "{:?}",
+                point
+            );
+        }
+    }
+
+    #[test]
+    fn batches_match_single_points() {
+        for dim in 2..7 {
+            assert_batch_matches(&SimplexNoise::new(dim), dim);
+        }
+        assert_batch_matches(&OctavedSimplexNoise::new(3, 4, 0.5), 3);
+        assert_batch_matches(&TiledOctavedSimplexNoise::octaved(2, 4, 0.5, 1.0, 0.5), 2);
+        assert_batch_matches(
+            &TiledOctavedSimplexNoise::skewed_octaved(2, 4, 0.5, 1.0, 0.5),
+            2,
+        );
+    }
+
+    /// Pins one dimensional noise for `V1` seeds to what it gave before the kernel was
+    /// versioned, lattice and all.
+    #[test]
+    fn golden_v1_one_dimension() {
+        let noise = SimplexNoise::new(1);
+        let seed = ProcSeed::with_version(&5u32, 0.0, HashVersion::V1);
+        let cases = [
+            (0.3, 0.41144976599999994),
+            (-0.45, -0.36405355503515635),
+            (1.7, 0.0),
+        ];
+        for (x, expected) in cases.iter() {
+            let actual = noise.get_noise(&seed, &vec![*x]);
+            assert!(
+                (actual - expected).abs() < 1e-12,
+                "at {}: expected {}, got {}",
+                x,
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn octaved_derivative() {
+        assert_derivative_matches(&OctavedSimplexNoise::new(3, 4, 0.5), 3);
+    }
+
+    #[test]
+    fn octaved_fixed_matches_generic() {
+        let seed = ProcSeed::new(&"octaved", 0.5);
+        for dim in 2..6 {
+            let noise = OctavedSimplexNoise::new(dim, 5, 0.5);
+            for k in 0..50 {
+                let point: Vec<f64> = (0..dim)
+                    .map(|i| ((k as f64 * 0.737 + i as f64 * 0.291).fract() - 0.5) * 20.0)
+                    .collect();
+                let (generic, generic_der) = noise.get_generic(&seed, &point);
+                let (fixed, fixed_der) = noise.get(&seed, &point);
+                assert_eq!(generic.to_bits(), fixed.to_bits(), "{:?}", point);
+                assert_eq!(generic.to_bits(), noise.get_noise(&seed, &point).to_bits());
+                assert_eq!(generic_der, fixed_der, "{:?}", point);
+            }
+        }
+    }
+
+    #[test]
+    fn tiled_derivative() {
+        assert_derivative_matches(&TiledOctavedSimplexNoise::octaved(2, 4, 0.5, 1.0, 0.5), 2);
+        assert_derivative_matches(
+            &TiledOctavedSimplexNoise::skewed_octaved(2, 4, 0.5, 1.0, 0.5),
+            2,
+        );
+    }
+
+    /// Points scattered over `[-span, span)` on every axis, from hashes so that the axes are
+    /// independent of each other.
+    fn scattered_points(dim: usize, count: usize, span: f64) -> Vec<Vec<f64>> {
+        (0..count)
+            .map(|k| {
+                (0..dim)
+                    .map(|i| {
+                        let hash = ProcSeed::new(&(k, i), 0.0).get();
+                        (hash as f64 / u64::MAX as f64 * 2.0 - 1.0) * span
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn values_spread_like_v1() {
+        for dim in 2..6 {
+            let noise = SimplexNoise::new(dim);
+            let points = scattered_points(dim, 20000, 50.0);
+            let statistics = |version| {
+                let seed = ProcSeed::with_version(&"statistics", 0.0, version);
+                let values: Vec<f64> = points.iter().map(|p| noise.get_noise(&seed, p)).collect();
+                let count = values.len() as f64;
+                let mean = values.iter().sum::<f64>() / count;
+                let rms = (values.iter().map(|v| v * v).sum::<f64>() / count).sqrt();
+                let max = values.iter().fold(0.0f64, |max, v| max.max(v.abs()));
+                (mean, rms, max)
+            };
+            let (mean, rms, max) = statistics(HashVersion::V2);
+            let (_, v1_rms, _) = statistics(HashVersion::V1);
+            assert!(mean.abs() < 0.02, "{} dimensions: mean {}", dim, mean);
+            assert!(0.6 < max && max < 1.7, "{} dimensions: max {}", dim, max);
+            assert!(
+                (rms / v1_rms - 1.0).abs() < 0.1,
+                "{} dimensions: rms {} against {} for V1",
+                dim,
+                rms,
+                v1_rms
+            );
+        }
+    }
+
+    /// The largest change in the noise across a hair's width of a boundary: a face of the
+    /// skewed hypercube, where one skewed coordinate is a whole number, or a face between two
+    /// of its simplices, where two skewed coordinates have the same fraction.
+    fn largest_jump(dim: usize, version: HashVersion) -> f64 {
+        let noise = SimplexNoise::new(dim);
+        let seed = ProcSeed::with_version(&"boundaries", 0.0, version);
+        let eps = 1e-9;
+        let mut jump = 0.0f64;
+        for (k, point) in scattered_points(dim, 2000, 10.0).iter().enumerate() {
+            let (i, j) = (k % dim, (k + 1) % dim);
+            let sum: f64 = point.iter().sum();
+            let skew = noise.get_kernel(&seed).skew;
+            let skewed: Vec<f64> = point.iter().map(|x| x + sum * skew).collect();
+
+            let mut cube_face = point.clone();
+            cube_face[i] = (skewed[i].round() - (sum - point[i]) * skew) / (1.0 + skew);
+            let mut step = vec![0.0; dim];
+            step[i] = eps;
+            let mut boundaries = vec![(cube_face, step)];
+
+            // moving along i and against j leaves the skew of the point as it is
+            let shift = (skewed[j] - skewed[j].floor() - skewed[i] + skewed[i].floor()) / 2.0;
+            let mut simplex_face = point.clone();
+            simplex_face[i] += shift;
+            simplex_face[j] -= shift;
+            let mut step = vec![0.0; dim];
+            step[i] = eps;
+            step[j] = -eps;
+            boundaries.push((simplex_face, step));
+
+            for (boundary, step) in boundaries {
+                let below: Vec<f64> = boundary.iter().zip(&step).map(|(x, s)| x - s).collect();
+                let above: Vec<f64> = boundary.iter().zip(&step).map(|(x, s)| x + s).collect();
+                let change = noise.get_noise(&seed, &above) - noise.get_noise(&seed, &below);
+                jump = jump.max(change.abs());
+            }
+        }
+        jump
+    }
+
+    #[test]
+    fn continuous_across_simplex_boundaries() {
+        for dim in 1..7 {
+            let jump = largest_jump(dim, HashVersion::V2);
+            assert!(jump < 1e-6, "{} dimensions: jump of {}", dim, jump);
+        }
+        // the kernel V1 seeds keep reaches past the simplex, which shows as hard straight edges
+        assert!(largest_jump(5, HashVersion::V1) > 0.01);
+    }
+
+    #[test]
+    fn isotropic() {
+        // the mean squared change over a short step should not depend on its direction
+        for dim in 2..6 {
+            let noise = SimplexNoise::new(dim);
+            let seed = ProcSeed::new(&"isotropy", 0.0);
+            let points = scattered_points(dim, 10000, 50.0);
+            let variogram = |angle: f64| {
+                let changes = points.iter().map(|p| {
+                    let mut q = p.clone();
+                    q[0] += 0.1 * angle.cos();
+                    q[1] += 0.1 * angle.sin();
+                    (noise.get_noise(&seed, &q) - noise.get_noise(&seed, p)).powi(2)
+                });
+                changes.sum::<f64>() / points.len() as f64
+            };
+            let directions: Vec<f64> = (0..4).map(|a| variogram(a as f64 * PI / 4.0)).collect();
+            let mean = directions.iter().sum::<f64>() / directions.len() as f64;
+            for (a, v) in directions.iter().enumerate() {
+                assert!(
+                    (v / mean - 1.0).abs() < 0.1,
+                    "{} dimensions, {} degrees: {} against a mean of {}",
+                    dim,
+                    a * 45,
+                    v,
+                    mean
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gradients_are_picked_evenly() {
+        for dim in 2..6 {
+            let grads = SimplexNoise::new(dim).grads;
+            let per_gradient = 500;
+            let mut picks = vec![0usize; grads.len()];
+            for k in 0..grads.len() * per_gradient {
+                // hashes as the vertices of a simplex get them
+                let hash = ProcSeed::new(&"gradients", 0.0)
+                    .derive(&vec![k as i64, -7])
+                    .get();
+                picks[gradient_index(HashVersion::V2, hash, grads.len())] += 1;
+            }
+            // chi-squared against an even spread, allowing five standard deviations
+            let expected = per_gradient as f64;
+            let chi_squared: f64 = picks
+                .iter()
+                .map(|&p| (p as f64 - expected).powi(2) / expected)
+                .sum();
+            let freedom = (grads.len() - 1) as f64;
+            assert!(
+                chi_squared < freedom + 5.0 * (2.0 * freedom).sqrt(),
+                "{} dimensions: {:?}",
+                dim,
+                picks
+            );
+
+            // the gradients themselves cancel out, so no direction is favoured
+            for i in 0..dim {
+                assert_eq!(grads.iter().map(|g| g[i]).sum::<f64>(), 0.0);
+            }
+        }
+        for version in [HashVersion::V1, HashVersion::V2].iter() {
+            assert_eq!(gradient_index(*version, 0, 80), 0);
+            assert_eq!(gradient_index(*version, u64::MAX, 80), 79);
+        }
     }
 }
@@ -0,0 +1,55 @@
+use super::{fade, lattice_cell, unit_hash};
+use crate::procgen::procseed::ProcSeed;
+
+/// The simplest lattice noise: every lattice point gets a value in `-1.0..=1.0` by hashing it,
+/// and points in between blend the corners of their cell with `fade`. Blockier than gradient
+/// noise, as its features line up with the lattice.
+pub struct ValueNoise {
+    pub dim: usize,
+}
+
+impl ValueNoise {
+    pub fn new(dim: usize) -> ValueNoise {
+        ValueNoise { dim: dim }
+    }
+}
+
+impl super::Noise for ValueNoise {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        let (cell, frac) = lattice_cell(self.dim, point);
+        let faded: Vec<f64> = frac.iter().map(|t| fade(*t)).collect();
+        let mut corner = cell.clone();
+        let mut noise = 0.0;
+        for c in 0..(1 << self.dim) {
+            let mut weight = 1.0;
+            for i in 0..self.dim {
+                let bit = (c >> i) & 1;
+                corner[i] = cell[i] + bit;
+                weight *= if bit == 1 { faded[i] } else { 1.0 - faded[i] };
+            }
+            noise += weight * (unit_hash(&seed.derive(&corner)) * 2.0 - 1.0);
+        }
+        noise
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{test_points, Noise};
+    use super::*;
+
+    #[test]
+    fn hashed_corners_blend_smoothly() {
+        let seed = ProcSeed::new(&"value", 0.0);
+        let noise = ValueNoise::new(2);
+        let corner = vec![3.0, -2.0];
+        let expected = unit_hash(&seed.derive(&vec![3i64, -2])) * 2.0 - 1.0;
+        assert_eq!(noise.get_noise(&seed, &corner), expected);
+        let gap = noise.get_noise(&seed, &vec![3.0 - 1e-9, -1.5])
+            - noise.get_noise(&seed, &vec![3.0 + 1e-9, -1.5]);
+        assert!(gap.abs() < 1e-6);
+        for point in test_points(500, 9.0) {
+            assert!(noise.get_noise(&seed, &point).abs() <= 1.0);
+        }
+    }
+}
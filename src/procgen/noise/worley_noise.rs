@@ -0,0 +1,189 @@
+use super::{lattice_cell, unit_hash};
+use crate::procgen::procseed::ProcSeed;
+use serde::{Deserialize, Serialize};
+
+/// Which of the distances found by `WorleyNoise::get_cells` the noise gives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WorleyOutput {
+    /// Distance to the nearest feature point, which is lowest at the centres of cells.
+    F1,
+    /// Distance to the second nearest feature point.
+    F2,
+    /// `F2 - F1`, which is zero along the borders between cells and so draws their outlines.
+    F2MinusF1,
+    /// A value in `-1.0..=1.0` that is the same across each cell, for flat colouring.
+    CellId,
+}
+
+/// What `WorleyNoise` finds around a point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorleyCells {
+    /// The lattice cell owning the nearest feature point, and so the region the point is in.
+    pub nearest: Vec<i64>,
+    /// A hash identifying the nearest cell, the same for every point in its region.
+    pub id: u64,
+    pub f1: f64,
+    pub f2: f64,
+}
+
+/// Cellular noise: every cell of the integer lattice holds one feature point, placed by hashing
+/// the cell, and space is divided into the regions closest to each. Distances are Euclidean and
+/// in lattice units, so F1 and F2 are not scaled to `-1.0..1.0` like other noise.
+pub struct WorleyNoise {
+    pub dim: usize,
+    pub output: WorleyOutput,
+}
+
+impl WorleyNoise {
+    pub fn new(dim: usize, output: WorleyOutput) -> WorleyNoise {
+        // without an axis there is no lattice to search, and `get_cells` would never stop
+        assert!(dim > 0, "worley noise needs at least one dimension");
+        WorleyNoise {
+            dim: dim,
+            output: output,
+        }
+    }
+
+    /// The feature point of a lattice cell.
+    fn feature_point(&self, seed: &ProcSeed, cell: &[i64]) -> Vec<f64> {
+        let cell_seed = seed.derive(&cell);
+        (0..self.dim)
+            .map(|i| cell[i] as f64 + unit_hash(&cell_seed.derive(&i)))
+            .collect()
+    }
+
+    /// Finds the two nearest feature points by searching rings of cells around the point's own,
+    /// stopping once no feature point in the next ring could be nearer than the second nearest
+    /// so far. The cells `k` steps away are at least `k - 1` away, as each feature point lies
+    /// within its cell.
+    pub fn get_cells(&self, seed: &ProcSeed, point: &Vec<f64>) -> WorleyCells {
+        let (cell, _) = lattice_cell(self.dim, point);
+        let mut nearest = cell.clone();
+        let mut f1 = f64::INFINITY;
+        let mut f2 = f64::INFINITY;
+        let mut k: usize = 0;
+        while f2 > (k as f64 - 1.0).max(0.0) {
+            let side = 2 * k + 1;
+            let mut neighbour = cell.clone();
+            for n in 0..side.pow(self.dim as u32) {
+                // visit every cell of the cube `k` steps across, skipping its inside, which
+                // earlier rings have covered
+                let mut ring = false;
+                let mut rest = n;
+                for i in 0..self.dim {
+                    let offset = (rest % side) as i64 - k as i64;
+                    rest /= side;
+                    ring |= offset.abs() == k as i64;
+                    neighbour[i] = cell[i] + offset;
+                }
+                if !ring {
+                    continue;
+                }
+                let feature = self.feature_point(seed, &neighbour);
+                let distance = (0..self.dim)
+                    .map(|i| (feature[i] - point[i]).powi(2))
+                    .sum::<f64>()
+                    .sqrt();
+                if distance < f1 {
+                    f2 = f1;
+                    f1 = distance;
+                    nearest.clone_from(&neighbour);
+                } else if distance < f2 {
+                    f2 = distance;
+                }
+            }
+            k += 1;
+        }
+        WorleyCells {
+            id: seed.derive(&nearest).get(),
+            nearest: nearest,
+            f1: f1,
+            f2: f2,
+        }
+    }
+}
+
+impl super::Noise for WorleyNoise {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        let cells = self.get_cells(seed, point);
+        match self.output {
+            WorleyOutput::F1 => cells.f1,
+            WorleyOutput::F2 => cells.f2,
+            WorleyOutput::F2MinusF1 => cells.f2 - cells.f1,
+            WorleyOutput::CellId => cells.id as f64 / u64::MAX as f64 * 2.0 - 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Noise;
+    use super::*;
+
+    /// The two nearest feature points among every cell within 3 steps.
+    fn brute_force(noise: &WorleyNoise, seed: &ProcSeed, point: &[f64]) -> (f64, f64) {
+        let (cell, _) = lattice_cell(noise.dim, point);
+        let mut distances = Vec::new();
+        for n in 0..7usize.pow(noise.dim as u32) {
+            let neighbour: Vec<i64> = (0..noise.dim)
+                .map(|i| cell[i] + (n / 7usize.pow(i as u32) % 7) as i64 - 3)
+                .collect();
+            let feature = noise.feature_point(seed, &neighbour);
+            distances.push(
+                (0..noise.dim)
+                    .map(|i| (feature[i] - point[i]).powi(2))
+                    .sum::<f64>()
+                    .sqrt(),
+            );
+        }
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        (distances[0], distances[1])
+    }
+
+    #[test]
+    fn finds_the_nearest_feature_points() {
+        let seed = ProcSeed::new(&"worley", 0.0);
+        for dim in 1..4 {
+            let noise = WorleyNoise::new(dim, WorleyOutput::F1);
+            for k in 0..200 {
+                let point: Vec<f64> = (0..dim)
+                    .map(|i| ((k as f64 * 0.754 + i as f64 * 0.317).fract() - 0.5) * 12.0)
+                    .collect();
+                let cells = noise.get_cells(&seed, &point);
+                assert_eq!((cells.f1, cells.f2), brute_force(&noise, &seed, &point));
+                assert_eq!(noise.get_noise(&seed, &point), cells.f1);
+            }
+        }
+    }
+
+    #[test]
+    fn outputs_agree_with_cells() {
+        let seed = ProcSeed::new(&"worley", 0.0);
+        let point = vec![4.3, -7.9];
+        let cells = WorleyNoise::new(2, WorleyOutput::F1).get_cells(&seed, &point);
+        let get = |output| WorleyNoise::new(2, output).get_noise(&seed, &point);
+        assert_eq!(get(WorleyOutput::F2), cells.f2);
+        assert_eq!(get(WorleyOutput::F2MinusF1), cells.f2 - cells.f1);
+        assert!(cells.f2 - cells.f1 >= 0.0);
+
+        // every point between a feature point and the query is in the same region
+        let noise = WorleyNoise::new(2, WorleyOutput::CellId);
+        let feature = noise.feature_point(&seed, &cells.nearest);
+        let id = get(WorleyOutput::CellId);
+        for t in 1..10 {
+            let t = t as f64 / 10.0;
+            let between = vec![
+                point[0] + (feature[0] - point[0]) * t,
+                point[1] + (feature[1] - point[1]) * t,
+            ];
+            assert_eq!(noise.get_cells(&seed, &between).id, cells.id);
+            assert_eq!(noise.get_noise(&seed, &between), id);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_zero_dimensions() {
+        WorleyNoise::new(0, WorleyOutput::F1);
+    }
+}
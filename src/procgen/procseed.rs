@@ -1,16 +1,68 @@
-use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fmt;
 use std::fmt::Debug;
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
+/// Identifies the hash function a seed was created with, and the noise built on it, so that a
+/// seed written down today keeps producing the same world after either changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HashVersion {
+    /// SipHash-1-3 with an all-zero key over little-endian integers, with `usize`/`isize`
+    /// widened to 64 bits. On 64-bit little-endian targets this matches the
+    /// `std::collections::hash_map::DefaultHasher` of the Rust releases the first maps were
+    /// generated with. Simplex noise keeps its original kernel, which jumps along simplex
+    /// boundaries above two dimensions.
+    V1,
+    /// The hash of `V1`, with simplex noise that is continuous across simplex boundaries.
+    V2,
+}
+
+impl HashVersion {
+    pub const CURRENT: HashVersion = HashVersion::V2;
+
+    pub fn number(&self) -> u32 {
+        match self {
+            HashVersion::V1 => 1,
+            HashVersion::V2 => 2,
+        }
+    }
+
+    pub fn from_number(number: u32) -> Option<HashVersion> {
+        match number {
+            1 => Some(HashVersion::V1),
+            2 => Some(HashVersion::V2),
+            _ => None,
+        }
+    }
+
+    pub fn hash<T: Hash + ?Sized>(&self, t: &T) -> u64 {
+        match self {
+            HashVersion::V1 | HashVersion::V2 => {
+                let mut s = SipHasher13::new_with_keys(0, 0);
+                t.hash(&mut s);
+                s.finish()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct ProcSeed {
     hash: u64,
+    version: HashVersion,
     pub skew: f64,
 }
 
 impl ProcSeed {
     pub fn new<T: Hash>(t: &T, skew: f64) -> ProcSeed {
+        ProcSeed::with_version(t, skew, HashVersion::CURRENT)
+    }
+
+    pub fn with_version<T: Hash>(t: &T, skew: f64, version: HashVersion) -> ProcSeed {
         ProcSeed {
-            hash: hash(t),
+            hash: version.hash(t),
+            version: version,
             skew: skew,
         }
     }
@@ -23,14 +75,297 @@ impl ProcSeed {
         self.skew
     }
 
+    pub fn get_version(&self) -> HashVersion {
+        self.version
+    }
+
     pub fn derive<T: Hash + Debug>(&self, t: &T) -> ProcSeed {
         //println!("Deriving seed {:?} from {:?} by hashing {:?} to {:?}", hash(&(self.get() ^ hash(t))), self.get(), t, hash(t));
-        ProcSeed::new(&(self.get() ^ hash(t)), self.skew)
+        ProcSeed::with_version(
+            &(self.get() ^ self.version.hash(t)),
+            self.skew,
+            self.version,
+        )
     }
 }
 
-fn hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
+/// Crockford's base32 alphabet, which leaves out I, L, O and U so that seeds survive being read
+/// aloud or copied by hand.
+const SEED_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const SEED_HASH_LEN: usize = 13;
+
+/// Seeds are written as `<hash version>-<hash in base32>-<skew>`, e.g. `2-CR8J7PYDC92QG-0.5`.
+/// The skew is printed with the shortest representation that parses back to the same `f64`.
+impl fmt::Display for ProcSeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hash: String = (0..SEED_HASH_LEN)
+            .rev()
+            .map(|i| SEED_ALPHABET[((self.hash >> (i * 5)) & 0x1f) as usize] as char)
+            .collect();
+        write!(f, "{}-{}-{}", self.version.number(), hash, self.skew)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseSeedError {
+    Format,
+    Version(String),
+    Hash(String),
+    Skew(String),
+}
+
+impl fmt::Display for ParseSeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseSeedError::Format => write!(f, "seed must look like <version>-<hash>-<skew>"),
+            ParseSeedError::Version(v) => write!(f, "unknown seed version {:?}", v),
+            ParseSeedError::Hash(h) => write!(f, "invalid seed hash {:?}", h),
+            ParseSeedError::Skew(s) => write!(f, "invalid seed skew {:?}", s),
+        }
+    }
+}
+
+impl Error for ParseSeedError {}
+
+impl FromStr for ProcSeed {
+    type Err = ParseSeedError;
+
+    fn from_str(s: &str) -> Result<ProcSeed, ParseSeedError> {
+        // splitting at most twice keeps the sign of a negative skew
+        let mut parts = s.trim().splitn(3, '-');
+        let (version, hash, skew) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(version), Some(hash), Some(skew)) => (version, hash, skew),
+            _ => return Err(ParseSeedError::Format),
+        };
+
+        let version = version
+            .parse()
+            .ok()
+            .and_then(HashVersion::from_number)
+            .ok_or_else(|| ParseSeedError::Version(version.to_string()))?;
+
+        if hash.len() != SEED_HASH_LEN {
+            return Err(ParseSeedError::Hash(hash.to_string()));
+        }
+        let mut value: u64 = 0;
+        for (i, c) in hash.chars().enumerate() {
+            let digit = match c.to_ascii_uppercase() {
+                'O' => 0,
+                'I' | 'L' => 1,
+                c => match SEED_ALPHABET.iter().position(|a| *a as char == c) {
+                    Some(digit) => digit as u64,
+                    None => return Err(ParseSeedError::Hash(hash.to_string())),
+                },
+            };
+            // 13 base32 digits carry 65 bits, so the leading digit may only use the low four
+            if i == 0 && digit > 0xf {
+                return Err(ParseSeedError::Hash(hash.to_string()));
+            }
+            value = (value << 5) | digit;
+        }
+
+        let skew = skew
+            .parse()
+            .map_err(|_| ParseSeedError::Skew(skew.to_string()))?;
+
+        Ok(ProcSeed {
+            hash: value,
+            version: version,
+            skew: skew,
+        })
+    }
+}
+
+/// SipHash-1-3 as specified at https://131002.net/siphash/, fed with a byte stream that does not
+/// depend on the target's endianness or pointer width.
+struct SipHasher13 {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    tail: u64,
+    ntail: usize,
+    length: usize,
+}
+
+impl SipHasher13 {
+    fn new_with_keys(k0: u64, k1: u64) -> SipHasher13 {
+        SipHasher13 {
+            v0: k0 ^ 0x736f6d6570736575,
+            v1: k1 ^ 0x646f72616e646f6d,
+            v2: k0 ^ 0x6c7967656e657261,
+            v3: k1 ^ 0x7465646279746573,
+            tail: 0,
+            ntail: 0,
+            length: 0,
+        }
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13);
+        self.v1 ^= self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16);
+        self.v3 ^= self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21);
+        self.v3 ^= self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17);
+        self.v1 ^= self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    fn compress(&mut self, m: u64) {
+        self.v3 ^= m;
+        self.round();
+        self.v0 ^= m;
+    }
+}
+
+impl Hasher for SipHasher13 {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.tail |= (*b as u64) << (8 * self.ntail);
+            self.ntail += 1;
+            if self.ntail == 8 {
+                let m = self.tail;
+                self.compress(m);
+                self.tail = 0;
+                self.ntail = 0;
+            }
+        }
+        self.length += bytes.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = SipHasher13 { ..*self };
+        let b = ((self.length as u64 & 0xff) << 56) | self.tail;
+        state.compress(b);
+        state.v2 ^= 0xff;
+        for _ in 0..3 {
+            state.round();
+        }
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16)
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32)
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128)
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stable_hashes() {
+        let v1 = HashVersion::V1;
+        assert_eq!(v1.hash(&0u32), 0xcc2247b79ac48af0);
+        assert_eq!(v1.hash(&7usize), 0x6634b0bda4fe8a7b);
+        assert_eq!(v1.hash(&u64::max_value()), 0x2f205be2fec8e38d);
+        assert_eq!(v1.hash(&"heightmap"), 0xb19756913f48ab0f);
+        assert_eq!(v1.hash(&vec![1i64, -2, 3]), 0x7b9d6d887318e193);
+        assert_eq!(v1.hash(&(3u8, "abc", 9i16)), 0x4467800fdfabf9b7);
+        assert_eq!(
+            v1.hash(&"a longer string of more than eight bytes"),
+            0x66f1deda03acac87
+        );
+        assert_eq!(HashVersion::V2.hash(&"heightmap"), v1.hash(&"heightmap"));
+    }
+
+    #[test]
+    fn stable_derived_seeds() {
+        let seed = ProcSeed::with_version(&0u32, 0.0, HashVersion::V1);
+        assert_eq!(seed.get_version(), HashVersion::V1);
+        assert_eq!(seed.derive(&"heightmap").get_version(), HashVersion::V1);
+        assert_eq!(seed.derive(&"heightmap").get(), 0xf79cdd53bbb132af);
+        assert_eq!(
+            seed.derive(&"heightmap").derive(&3usize).get(),
+            0xe412104d3c1f16cc
+        );
+    }
+
+    #[test]
+    fn seed_string_round_trip() {
+        let seeds = vec![
+            ProcSeed::new(&0u32, 0.0),
+            ProcSeed::new(&"nations", -1.25),
+            ProcSeed::new(&u64::max_value(), 0.1 + 0.2),
+            ProcSeed::new(&"heightmap", 1e-300).derive(&3usize),
+        ];
+        for seed in seeds {
+            let text = seed.to_string();
+            assert_eq!(text.parse::<ProcSeed>(), Ok(seed.clone()), "{}", text);
+            assert_eq!(
+                text.to_lowercase().parse::<ProcSeed>(),
+                Ok(seed),
+                "{}",
+                text
+            );
+        }
+        let v1 = ProcSeed::with_version(&0u32, 0.0, HashVersion::V1);
+        assert_eq!(v1.to_string(), "1-CR8J7PYDC92QG-0");
+        assert_eq!(ProcSeed::new(&0u32, 0.0).to_string(), "2-CR8J7PYDC92QG-0");
+    }
+
+    #[test]
+    fn bad_seed_strings() {
+        assert_eq!("".parse::<ProcSeed>(), Err(ParseSeedError::Format));
+        assert_eq!("1-00000".parse::<ProcSeed>(), Err(ParseSeedError::Format));
+        assert!(match "9-0000000000000-0".parse::<ProcSeed>() {
+            Err(ParseSeedError::Version(_)) => true,
+            _ => false,
+        });
+        assert!(match "1-G000000000000-0".parse::<ProcSeed>() {
+            Err(ParseSeedError::Hash(_)) => true,
+            _ => false,
+        });
+        assert!(match "1-000000000000U-0".parse::<ProcSeed>() {
+            Err(ParseSeedError::Hash(_)) => true,
+            _ => false,
+        });
+        assert!(match "1-0000000000000-north".parse::<ProcSeed>() {
+            Err(ParseSeedError::Skew(_)) => true,
+            _ => false,
+        });
+    }
 }
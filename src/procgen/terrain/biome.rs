@@ -0,0 +1,316 @@
+use super::MapBiome;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Whittaker-style biome classification: height picks a zone, temperature picks a band within
+/// the zone and precipitation picks a biome within the band. All values are in the 0..1 range
+/// returned by `Terrain`.
+///
+/// Tables are written in RON, for example
+///
+/// ```ron
+/// (
+///     water_level: 0.55,
+///     mountain_level: 0.7,
+///     water: [
+///         (below: 0.225, precipitation: [(biome: Ice)]),
+///         (precipitation: [(biome: Water)]),
+///     ],
+///     ...
+/// )
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BiomeTable {
+    pub water_level: f64,
+    pub mountain_level: f64,
+    /// Bands for heights below `water_level`.
+    pub water: Vec<TemperatureBand>,
+    /// Bands for heights from `water_level` up to `mountain_level`.
+    pub land: Vec<TemperatureBand>,
+    /// Bands for heights from `mountain_level` up.
+    pub mountain: Vec<TemperatureBand>,
+    /// Biomes a loaded table has no style for get the built-in style.
+    #[serde(deserialize_with = "with_default_styles")]
+    pub styles: BTreeMap<MapBiome, BiomeStyle>,
+}
+
+/// Bands are checked in order and the first one whose `below` bound exceeds the value is used;
+/// leaving out `below` makes a band catch everything the earlier bands did not.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureBand {
+    #[serde(default = "unbounded")]
+    pub below: f64,
+    pub precipitation: Vec<PrecipitationBand>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PrecipitationBand {
+    #[serde(default = "unbounded")]
+    pub below: f64,
+    pub biome: MapBiome,
+}
+
+/// How a biome is drawn. Colours are RGB in the 0..1 range.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BiomeStyle {
+    pub glyph: char,
+    pub colour: [f64; 3],
+    pub background: [f64; 3],
+    /// If set, the biome is shaded from this colour at height 0 to `colour` at the water level.
+    #[serde(default)]
+    pub deep_colour: Option<[f64; 3]>,
+}
+
+/// Drawn for biomes a table was built without a style for.
+const MISSING_STYLE: BiomeStyle = BiomeStyle {
+    glyph: '?',
+    colour: [1.0, 0.0, 1.0],
+    background: [0.0, 0.0, 0.0],
+    deep_colour: None,
+};
+
+fn unbounded() -> f64 {
+    f64::INFINITY
+}
+
+fn with_default_styles<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<MapBiome, BiomeStyle>, D::Error> {
+    let mut styles = BiomeTable::default().styles;
+    styles.extend(BTreeMap::<MapBiome, BiomeStyle>::deserialize(deserializer)?);
+    Ok(styles)
+}
+
+impl BiomeTable {
+    pub fn from_ron(text: &str) -> Result<BiomeTable, Box<dyn Error>> {
+        Ok(ron::de::from_str(text)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<BiomeTable, Box<dyn Error>> {
+        BiomeTable::from_ron(&fs::read_to_string(path)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String, Box<dyn Error>> {
+        Ok(ron::ser::to_string_pretty(self, Default::default())?)
+    }
+
+    pub fn classify(&self, height: f64, temperature: f64, precipitation: f64) -> MapBiome {
+        let zone = if height < self.water_level {
+            &self.water
+        } else if height < self.mountain_level {
+            &self.land
+        } else {
+            &self.mountain
+        };
+        zone.iter()
+            .find(|band| temperature < band.below)
+            .and_then(|band| {
+                band.precipitation
+                    .iter()
+                    .find(|band| precipitation < band.below)
+            })
+            .map(|band| band.biome)
+            .unwrap_or(MapBiome::Empty)
+    }
+
+    /// Loaded tables already have the built-in style for every biome they leave out, so only
+    /// tables built in code without one get the placeholder.
+    pub fn style(&self, biome: MapBiome) -> &BiomeStyle {
+        self.styles.get(&biome).unwrap_or(&MISSING_STYLE)
+    }
+}
+
+impl BiomeStyle {
+    pub fn new(glyph: char, colour: [f64; 3], background: [f64; 3]) -> BiomeStyle {
+        BiomeStyle {
+            glyph: glyph,
+            colour: colour,
+            background: background,
+            deep_colour: None,
+        }
+    }
+}
+
+impl TemperatureBand {
+    fn new(below: f64, precipitation: Vec<PrecipitationBand>) -> TemperatureBand {
+        TemperatureBand {
+            below: below,
+            precipitation: precipitation,
+        }
+    }
+
+    fn only(below: f64, biome: MapBiome) -> TemperatureBand {
+        TemperatureBand::new(below, vec![PrecipitationBand::new(f64::INFINITY, biome)])
+    }
+}
+
+impl PrecipitationBand {
+    fn new(below: f64, biome: MapBiome) -> PrecipitationBand {
+        PrecipitationBand {
+            below: below,
+            biome: biome,
+        }
+    }
+}
+
+impl Default for BiomeTable {
+    /// fudged implementation of https://upload.wikimedia.org/wikipedia/commons/6/68/Climate_influence_on_terrestrial_biome.svg
+    fn default() -> BiomeTable {
+        let freezing = 0.225;
+        let cold = 0.3;
+        let temperate = 0.6;
+
+        let arid = 0.45;
+        let moist = 0.5;
+        let wet = 0.6;
+
+        let band = TemperatureBand::new;
+        let biome = PrecipitationBand::new;
+
+        let mut styles = BTreeMap::new();
+        styles.insert(
+            MapBiome::Empty,
+            BiomeStyle::new('.', [1.0, 1.0, 1.0], [0.0, 0.0, 0.0]),
+        );
+        styles.insert(
+            MapBiome::Water,
+            BiomeStyle {
+                deep_colour: Some([0.0, 0.0, 0.3]),
+                ..BiomeStyle::new('~', [0.0, 0.4, 0.9], [0.0, 0.0, 0.4])
+            },
+        );
+        styles.insert(
+            MapBiome::Ice,
+            BiomeStyle::new('.', [0.7, 0.7, 1.0], [1.0, 1.0, 1.0]),
+        );
+        styles.insert(
+            MapBiome::Tundra,
+            BiomeStyle::new(':', [0.5, 0.8, 0.4], [0.8, 0.9, 0.8]),
+        );
+        styles.insert(
+            MapBiome::BorealForest,
+            BiomeStyle::new('T', [0.4, 0.7, 0.4], [0.6, 0.9, 0.6]),
+        );
+        styles.insert(
+            MapBiome::Shrubland,
+            BiomeStyle::new(';', [0.5, 0.6, 0.2], [0.6, 0.8, 0.4]),
+        );
+        styles.insert(
+            MapBiome::TemperateGrassland,
+            BiomeStyle::new('.', [0.3, 0.7, 0.2], [0.6, 0.8, 0.5]),
+        );
+        styles.insert(
+            MapBiome::TemperateRainforest,
+            BiomeStyle::new('T', [0.2, 0.6, 0.4], [0.5, 0.8, 0.5]),
+        );
+        styles.insert(
+            MapBiome::TemperateSeasonalForest,
+            BiomeStyle::new('t', [0.6, 0.8, 0.3], [0.6, 0.8, 0.4]),
+        );
+        styles.insert(
+            MapBiome::TropicalRainforest,
+            BiomeStyle::new('T', [0.2, 0.8, 0.2], [0.1, 0.5, 0.1]),
+        );
+        styles.insert(
+            MapBiome::TropicalSeasonalForest,
+            BiomeStyle::new('t', [0.5, 0.7, 0.1], [0.2, 0.6, 0.1]),
+        );
+        styles.insert(
+            MapBiome::Savannah,
+            BiomeStyle::new('.', [0.5, 0.7, 0.1], [0.6, 0.8, 0.4]),
+        );
+        styles.insert(
+            MapBiome::Desert,
+            BiomeStyle::new('~', [0.9, 0.8, 0.2], [0.8, 0.6, 0.6]),
+        );
+        styles.insert(
+            MapBiome::Mountain,
+            BiomeStyle::new('^', [0.8, 0.8, 0.8], [0.4, 0.4, 0.4]),
+        );
+
+        BiomeTable {
+            water_level: 0.55,
+            mountain_level: 0.7,
+            water: vec![
+                TemperatureBand::only(freezing, MapBiome::Ice),
+                TemperatureBand::only(f64::INFINITY, MapBiome::Water),
+            ],
+            land: vec![
+                TemperatureBand::only(freezing, MapBiome::Tundra),
+                band(
+                    cold,
+                    vec![
+                        biome(arid, MapBiome::TemperateGrassland),
+                        biome(moist, MapBiome::Shrubland),
+                        biome(f64::INFINITY, MapBiome::BorealForest),
+                    ],
+                ),
+                band(
+                    temperate,
+                    vec![
+                        biome(arid, MapBiome::TemperateGrassland),
+                        biome(moist, MapBiome::Shrubland),
+                        biome(wet, MapBiome::TemperateSeasonalForest),
+                        biome(f64::INFINITY, MapBiome::TemperateRainforest),
+                    ],
+                ),
+                band(
+                    f64::INFINITY,
+                    vec![
+                        biome(arid, MapBiome::Desert),
+                        biome(moist, MapBiome::Savannah),
+                        biome(wet, MapBiome::TropicalSeasonalForest),
+                        biome(f64::INFINITY, MapBiome::TropicalRainforest),
+                    ],
+                ),
+            ],
+            mountain: vec![TemperatureBand::only(f64::INFINITY, MapBiome::Mountain)],
+            styles: styles,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_table_round_trips_through_ron() {
+        let table = BiomeTable::default();
+        let text = table.to_ron().unwrap();
+        assert_eq!(BiomeTable::from_ron(&text).unwrap(), table);
+    }
+
+    #[test]
+    fn bands_without_bounds_catch_the_rest() {
+        let table = BiomeTable::from_ron(
+            "(
+                water_level: 0.5,
+                mountain_level: 0.9,
+                water: [(precipitation: [(biome: Water)])],
+                land: [
+                    (below: 0.5, precipitation: [(below: 0.5, biome: Tundra), (biome: Shrubland)]),
+                    (precipitation: [(biome: Desert)]),
+                ],
+                mountain: [],
+                styles: {},
+            )",
+        )
+        .unwrap();
+        assert_eq!(table.classify(0.1, 0.0, 0.0), MapBiome::Water);
+        assert_eq!(table.classify(0.6, 0.1, 0.1), MapBiome::Tundra);
+        assert_eq!(table.classify(0.6, 0.1, 0.9), MapBiome::Shrubland);
+        assert_eq!(table.classify(0.6, 0.9, 0.1), MapBiome::Desert);
+        assert_eq!(table.classify(0.95, 0.5, 0.5), MapBiome::Empty);
+        assert_eq!(table.style(MapBiome::Desert).glyph, '~');
+        assert_eq!(table.styles.len(), BiomeTable::default().styles.len());
+
+        let mut table = table;
+        table.styles.clear();
+        assert_eq!(table.style(MapBiome::Desert).glyph, '?');
+    }
+}
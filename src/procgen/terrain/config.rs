@@ -0,0 +1,263 @@
+use crate::procgen::noise::fractal::FractalKind;
+use crate::procgen::noise::graph::{GraphError, NoiseGraph};
+use crate::procgen::terrain::TERRAIN_DIM;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Parameters of one layer of `TiledOctavedSimplexNoise::skewed_octaved`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoiseLayerConfig {
+    pub octaves: usize,
+    /// Amplitude and wavelength multiplier from one octave to the next.
+    pub octave_factor: f64,
+    /// Distance in terrain space after which the layer repeats.
+    pub tile_distance: f64,
+    /// Radius of the torus the layer is sampled on; larger values give smaller features.
+    pub scale: f64,
+}
+
+/// Noise graphs that replace the simplex layers of a `TerrainConfig` they are set for, for layers
+/// that need more than octaves of simplex noise. Each is sampled in terrain space, and must
+/// make itself repeat along both axes for the world to wrap.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LayerGraphs {
+    #[serde(default)]
+    pub heightmap: Option<NoiseGraph>,
+    #[serde(default)]
+    pub moisturemap: Option<NoiseGraph>,
+    #[serde(default)]
+    pub temperatureperturbancemap: Option<NoiseGraph>,
+    #[serde(default)]
+    pub maptexturemap: Option<NoiseGraph>,
+}
+
+/// Everything `TiledWorldTerrain` is built from, so that worlds can be tuned and shipped as
+/// presets without code changes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TerrainConfig {
+    pub heightmap: NoiseLayerConfig,
+    pub moisturemap: NoiseLayerConfig,
+    pub temperatureperturbancemap: NoiseLayerConfig,
+    pub maptexturemap: NoiseLayerConfig,
+    /// Height below which terrain is water. Replaces the `water_level` of the default biome
+    /// table.
+    pub sea_level: f64,
+    /// Weight of the latitude-based temperature.
+    pub temperature_base_weight: f64,
+    /// Weight of the noise-based temperature perturbance.
+    pub temperature_perturbance_weight: f64,
+    /// Added to every temperature after weighting.
+    #[serde(default)]
+    pub temperature_offset: f64,
+    #[serde(default)]
+    pub graphs: LayerGraphs,
+}
+
+impl NoiseLayerConfig {
+    pub fn new(octaves: usize, octave_factor: f64, tile_distance: f64, scale: f64) -> Self {
+        NoiseLayerConfig {
+            octaves: octaves,
+            octave_factor: octave_factor,
+            tile_distance: tile_distance,
+            scale: scale,
+        }
+    }
+
+    /// The graph of the skewed `TiledOctavedSimplexNoise` these parameters describe.
+    pub fn to_graph(&self) -> NoiseGraph {
+        NoiseGraph::Tiled {
+            source: Box::new(NoiseGraph::OctavedSimplex {
+                dim: 5,
+                octaves: self.octaves,
+                octave_factor: self.octave_factor,
+            }),
+            dim: 2,
+            tile_distance: self.tile_distance,
+            scale: self.scale,
+            skewed: true,
+        }
+    }
+
+    /// `graph` if one is set for the layer, or else the graph of these parameters.
+    pub fn layer_graph(&self, graph: &Option<NoiseGraph>) -> NoiseGraph {
+        graph.clone().unwrap_or_else(|| self.to_graph())
+    }
+}
+
+impl TerrainConfig {
+    pub const PRESETS: [&'static str; 5] =
+        ["default", "archipelago", "pangaea", "ice-age", "highlands"];
+
+    pub fn preset(name: &str) -> Option<TerrainConfig> {
+        match name {
+            "default" => Some(TerrainConfig::default()),
+            "archipelago" => Some(TerrainConfig::archipelago()),
+            "pangaea" => Some(TerrainConfig::pangaea()),
+            "ice-age" => Some(TerrainConfig::ice_age()),
+            "highlands" => Some(TerrainConfig::highlands()),
+            _ => None,
+        }
+    }
+
+    /// Many small islands: a higher frequency heightmap under a raised sea.
+    pub fn archipelago() -> TerrainConfig {
+        let default = TerrainConfig::default();
+        TerrainConfig {
+            heightmap: NoiseLayerConfig {
+                scale: 1.2,
+                ..default.heightmap
+            },
+            sea_level: 0.6,
+            ..default
+        }
+    }
+
+    /// A few large continents: a low frequency heightmap under a lowered sea.
+    pub fn pangaea() -> TerrainConfig {
+        let default = TerrainConfig::default();
+        TerrainConfig {
+            heightmap: NoiseLayerConfig {
+                scale: 0.3,
+                octave_factor: 0.45,
+                ..default.heightmap
+            },
+            sea_level: 0.5,
+            ..default
+        }
+    }
+
+    /// A cold world whose seas have partly retreated into ice.
+    pub fn ice_age() -> TerrainConfig {
+        TerrainConfig {
+            sea_level: 0.5,
+            temperature_offset: -0.15,
+            ..TerrainConfig::default()
+        }
+    }
+
+    /// Rugged continents: the heightmap mixes its usual octaves with three tenths ridged noise,
+    /// stretched a little so that the ridges rise into mountain ranges inland.
+    pub fn highlands() -> TerrainConfig {
+        let default = TerrainConfig::default();
+        let heightmap = NoiseGraph::Tiled {
+            source: Box::new(NoiseGraph::Scale {
+                source: Box::new(NoiseGraph::Blend {
+                    a: Box::new(NoiseGraph::OctavedSimplex {
+                        dim: 5,
+                        octaves: 20,
+                        octave_factor: 0.5,
+                    }),
+                    b: Box::new(NoiseGraph::Fractal {
+                        source: Box::new(NoiseGraph::Simplex { dim: 5 }),
+                        kind: FractalKind::Ridged,
+                        octaves: 8,
+                        frequency: 2.0,
+                        lacunarity: 2.0,
+                        gain: 0.5,
+                    }),
+                    control: Box::new(NoiseGraph::Constant { value: -0.4 }),
+                }),
+                factor: 1.3,
+            }),
+            dim: 2,
+            tile_distance: default.heightmap.tile_distance,
+            scale: default.heightmap.scale,
+            skewed: true,
+        };
+        TerrainConfig {
+            graphs: LayerGraphs {
+                heightmap: Some(heightmap),
+                ..LayerGraphs::default()
+            },
+            ..default
+        }
+    }
+
+    /// Checks that the graph of every layer can be built for points in terrain space.
+    pub fn check(&self) -> Result<(), GraphError> {
+        let layers = [
+            (&self.heightmap, &self.graphs.heightmap),
+            (&self.moisturemap, &self.graphs.moisturemap),
+            (
+                &self.temperatureperturbancemap,
+                &self.graphs.temperatureperturbancemap,
+            ),
+            (&self.maptexturemap, &self.graphs.maptexturemap),
+        ];
+        for (config, graph) in layers.iter() {
+            config.layer_graph(graph).check(TERRAIN_DIM)?;
+        }
+        Ok(())
+    }
+
+    pub fn from_ron(text: &str) -> Result<TerrainConfig, Box<dyn Error>> {
+        let config: TerrainConfig = ron::de::from_str(text)?;
+        config.check()?;
+        Ok(config)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<TerrainConfig, Box<dyn Error>> {
+        TerrainConfig::from_ron(&fs::read_to_string(path)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String, Box<dyn Error>> {
+        Ok(ron::ser::to_string_pretty(self, Default::default())?)
+    }
+}
+
+impl Default for TerrainConfig {
+    fn default() -> TerrainConfig {
+        let tile_distance = 1.0;
+        TerrainConfig {
+            heightmap: NoiseLayerConfig::new(20, 0.5, tile_distance, 0.5),
+            moisturemap: NoiseLayerConfig::new(10, 0.5, tile_distance, 0.5),
+            temperatureperturbancemap: NoiseLayerConfig::new(5, 0.5, tile_distance, 1.0),
+            maptexturemap: NoiseLayerConfig::new(5, 0.5, tile_distance, 0.5),
+            sea_level: 0.55,
+            temperature_base_weight: 1.5,
+            temperature_perturbance_weight: 1.0,
+            temperature_offset: 0.0,
+            graphs: LayerGraphs::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn presets_round_trip_through_ron() {
+        for name in TerrainConfig::PRESETS.iter() {
+            let config = TerrainConfig::preset(name).unwrap();
+            let text = config.to_ron().unwrap();
+            assert_eq!(TerrainConfig::from_ron(&text).unwrap(), config, "{}", name);
+        }
+        assert_eq!(TerrainConfig::preset("atlantis"), None);
+    }
+
+    #[test]
+    fn configs_whose_layers_cannot_be_built_are_refused() {
+        let default = TerrainConfig::default();
+        let silent = TerrainConfig {
+            moisturemap: NoiseLayerConfig {
+                octaves: 0,
+                ..default.moisturemap
+            },
+            ..default.clone()
+        };
+        assert!(silent.check().is_err());
+        assert!(TerrainConfig::from_ron(&silent.to_ron().unwrap()).is_err());
+
+        let mismatched = TerrainConfig {
+            graphs: LayerGraphs {
+                heightmap: Some(NoiseGraph::Simplex { dim: 3 }),
+                ..LayerGraphs::default()
+            },
+            ..default
+        };
+        assert!(TerrainConfig::from_ron(&mismatched.to_ron().unwrap()).is_err());
+    }
+}
@@ -1,13 +1,22 @@
-use crate::procgen;
-use crate::procgen::noise::simplex_noise::SkewedTiledOctavedSimplexNoise;
-use crate::procgen::noise::Noise;
+pub mod biome;
+pub mod config;
+
+use crate::procgen::noise::graph::{BoxedNoise, NoiseGraph};
+use crate::procgen::noise::{batch_len, to_axes, Noise};
 use crate::procgen::procseed::ProcSeed;
 use std::f64::consts::PI;
 
+use biome::BiomeTable;
+use config::{NoiseLayerConfig, TerrainConfig};
 use quicksilver::graphics::Color;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 extern crate rand;
 
+/// Number of coordinates of a point in terrain space, which every layer is sampled at.
+pub const TERRAIN_DIM: usize = 2;
+
 pub trait Terrain {
     fn get_height(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64;
     fn get_precipitation(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64;
@@ -15,53 +24,279 @@ pub trait Terrain {
     fn get_map_texture(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64;
     fn get_biome(&self, seed: &ProcSeed, point: &Vec<f64>) -> MapBiome;
     fn render_qs(&self, seed: &ProcSeed, point: &Vec<f64>) -> (Color, Color);
+
+    fn get_sample(&self, seed: &ProcSeed, point: &Vec<f64>) -> TerrainSample {
+        TerrainSample {
+            height: self.get_height(seed, point),
+            temperature: self.get_temperature(seed, point),
+            precipitation: self.get_precipitation(seed, point),
+            biome: self.get_biome(seed, point),
+        }
+    }
+
+    /// Samples many points at once, given as one vector of coordinates per axis as in
+    /// `Noise::get_noise_batch`. Each sample is exactly what `get_sample` gives for its point.
+    fn get_sample_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<TerrainSample> {
+        let mut point = vec![0.0; axes.len()];
+        (0..batch_len(axes))
+            .map(|i| {
+                for (x, axis) in point.iter_mut().zip(axes) {
+                    *x = axis[i];
+                }
+                self.get_sample(seed, &point)
+            })
+            .collect()
+    }
+
+    /// Samples a `width` by `height` grid of cells, a row per batch and rows in parallel, with
+    /// `point(x, y)` giving the terrain point of each cell. Every cell is sampled exactly as
+    /// `get_sample` would, so the results do not depend on how the work is split up.
+    fn sample_region<F>(
+        &self,
+        seed: &ProcSeed,
+        width: usize,
+        height: usize,
+        point: F,
+    ) -> TerrainRegion
+    where
+        Self: Sync,
+        F: Fn(usize, usize) -> Vec<f64> + Sync,
+    {
+        let mut region = TerrainRegion::new(width, height);
+        if region.is_empty() {
+            return region;
+        }
+        let rows = region
+            .heights
+            .par_chunks_mut(width)
+            .zip(region.temperatures.par_chunks_mut(width))
+            .zip(region.precipitations.par_chunks_mut(width))
+            .zip(region.biomes.par_chunks_mut(width));
+        rows.enumerate()
+            .for_each(|(y, (((heights, temperatures), precipitations), biomes))| {
+                let points: Vec<Vec<f64>> = (0..width).map(|x| point(x, y)).collect();
+                let samples = self.get_sample_batch(seed, &to_axes(&points));
+                for (x, sample) in samples.into_iter().enumerate() {
+                    heights[x] = sample.height;
+                    temperatures[x] = sample.temperature;
+                    precipitations[x] = sample.precipitation;
+                    biomes[x] = sample.biome;
+                }
+            });
+        region
+    }
+}
+
+/// The terrain properties gameplay cares about at one point, for storing in a map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainSample {
+    pub height: f64,
+    pub temperature: f64,
+    pub precipitation: f64,
+    pub biome: MapBiome,
+}
+
+/// Terrain samples for a grid of cells, one buffer per property, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerrainRegion {
+    pub width: usize,
+    pub height: usize,
+    pub heights: Vec<f64>,
+    pub temperatures: Vec<f64>,
+    pub precipitations: Vec<f64>,
+    pub biomes: Vec<MapBiome>,
+}
+
+impl TerrainRegion {
+    /// A region of `width` by `height` empty cells, to be filled in.
+    fn new(width: usize, height: usize) -> TerrainRegion {
+        let cells = width * height;
+        TerrainRegion {
+            width: width,
+            height: height,
+            heights: vec![0.0; cells],
+            temperatures: vec![0.0; cells],
+            precipitations: vec![0.0; cells],
+            biomes: vec![MapBiome::Empty; cells],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.biomes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.biomes.is_empty()
+    }
+
+    /// The sample of the `i`th cell, counting row by row.
+    pub fn get(&self, i: usize) -> TerrainSample {
+        TerrainSample {
+            height: self.heights[i],
+            temperature: self.temperatures[i],
+            precipitation: self.precipitations[i],
+            biome: self.biomes[i],
+        }
+    }
+
+    /// Replaces the sample of the `i`th cell.
+    pub fn set(&mut self, i: usize, sample: TerrainSample) {
+        self.heights[i] = sample.height;
+        self.temperatures[i] = sample.temperature;
+        self.precipitations[i] = sample.precipitation;
+        self.biomes[i] = sample.biome;
+    }
+
+    pub fn samples<'a>(&'a self) -> impl Iterator<Item = TerrainSample> + 'a {
+        (0..self.len()).map(move |i| self.get(i))
+    }
 }
 
 pub struct TiledWorldTerrain {
-    heightmap: SkewedTiledOctavedSimplexNoise,
-    moisturemap: SkewedTiledOctavedSimplexNoise,
-    temperatureperturbancemap: SkewedTiledOctavedSimplexNoise,
-    maptexturemap: SkewedTiledOctavedSimplexNoise,
+    heightmap: Layer,
+    moisturemap: Layer,
+    temperatureperturbancemap: Layer,
+    maptexturemap: Layer,
+    biomes: BiomeTable,
+    temperature_base_weight: f64,
+    temperature_perturbance_weight: f64,
+    temperature_offset: f64,
+}
+
+/// The noise of one terrain layer, and the graph it was configured with so that it can be
+/// rebuilt with other tile distances and restored.
+struct Layer {
+    graph: NoiseGraph,
+    noise: BoxedNoise,
+}
+
+impl Layer {
+    /// `graph` if there is one, or else simplex noise as `config` describes. Configs are
+    /// checked when they are loaded, so a graph that cannot be built is a bug in the code that
+    /// made it.
+    fn new(config: &NoiseLayerConfig, graph: &Option<NoiseGraph>) -> Layer {
+        let graph = config.layer_graph(graph);
+        Layer {
+            noise: Layer::build(&graph),
+            graph: graph,
+        }
+    }
+
+    fn build(graph: &NoiseGraph) -> BoxedNoise {
+        graph
+            .build(TERRAIN_DIM)
+            .unwrap_or_else(|e| panic!("invalid terrain layer: {}", e))
+    }
+
+    fn set_tile_distance(&mut self, tile_distance: Option<f64>) {
+        self.noise = match tile_distance {
+            Some(tile_distance) => {
+                let mut graph = self.graph.clone();
+                graph.set_tile_distance(tile_distance);
+                Layer::build(&graph)
+            }
+            None => Layer::build(&self.graph),
+        };
+    }
+}
+
+impl Noise for Layer {
+    fn get_noise(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
+        self.noise.get_noise(seed, point)
+    }
+
+    fn get_noise_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<f64> {
+        self.noise.get_noise_batch(seed, axes)
+    }
 }
 
 impl TiledWorldTerrain {
     pub fn new() -> TiledWorldTerrain {
-        let tile_distance = 1.0;
+        TiledWorldTerrain::from_config(&TerrainConfig::default())
+    }
+
+    pub fn from_config(config: &TerrainConfig) -> TiledWorldTerrain {
+        let biomes = BiomeTable {
+            water_level: config.sea_level,
+            ..BiomeTable::default()
+        };
         TiledWorldTerrain {
-            // TODO: for some reason all these noise maps seem to glitch when zooming in, tending towards straight hard edges at 45 degree angles. Must be a float or rounding error. Investigate in noise functions.
-            heightmap: procgen::noise::simplex_noise::SkewedTiledOctavedSimplexNoise::new(
-                2,
-                20,
-                0.5,
-                tile_distance,
-                0.5,
-            ),
-            moisturemap: procgen::noise::simplex_noise::SkewedTiledOctavedSimplexNoise::new(
-                2,
-                10,
-                0.5,
-                tile_distance,
-                0.5,
-            ),
-            temperatureperturbancemap:
-                procgen::noise::simplex_noise::SkewedTiledOctavedSimplexNoise::new(
-                    2,
-                    5,
-                    0.5,
-                    tile_distance,
-                    1.0,
-                ),
-            maptexturemap: procgen::noise::simplex_noise::SkewedTiledOctavedSimplexNoise::new(
-                2,
-                5,
-                0.5,
-                tile_distance,
-                0.5,
+            heightmap: Layer::new(&config.heightmap, &config.graphs.heightmap),
+            moisturemap: Layer::new(&config.moisturemap, &config.graphs.moisturemap),
+            temperatureperturbancemap: Layer::new(
+                &config.temperatureperturbancemap,
+                &config.graphs.temperatureperturbancemap,
             ),
+            maptexturemap: Layer::new(&config.maptexturemap, &config.graphs.maptexturemap),
+            biomes: biomes,
+            temperature_base_weight: config.temperature_base_weight,
+            temperature_perturbance_weight: config.temperature_perturbance_weight,
+            temperature_offset: config.temperature_offset,
         }
     }
+
+    /// Heights below this are sea.
+    pub fn get_water_level(&self) -> f64 {
+        self.biomes.water_level
+    }
+
+    pub fn get_biome_table(&self) -> &BiomeTable {
+        &self.biomes
+    }
+
+    pub fn set_biome_table(&mut self, biomes: BiomeTable) {
+        self.biomes = biomes;
+    }
+
+    /// The colours `render_qs` gives a point of `biome` at `height` with map texture `texture`,
+    /// for drawing samples that did not come straight from the terrain.
+    pub fn render_biome(&self, biome: MapBiome, height: f64, texture: f64) -> (Color, Color) {
+        let style = self.biomes.style(biome);
+        let fg = match style.deep_colour {
+            Some(deep) => color_lerp(
+                &deep.to_vec(),
+                &style.colour.to_vec(),
+                (height / self.biomes.water_level).powi(3),
+            ),
+            None => style.colour.to_vec(),
+        };
+        (
+            color_to_cursive_color(&fg),
+            color_to_cursive_color(&color_brightness(&fg, 0.3 + 0.7 * texture)),
+        )
+    }
+
+    /// Makes every layer repeat after `tile_distance`, so that the terrain can be wrapped
+    /// seamlessly around a world of that circumference, or with `None` puts back the distances
+    /// the layers were configured with.
+    pub fn set_tile_distance(&mut self, tile_distance: Option<f64>) {
+        self.heightmap.set_tile_distance(tile_distance);
+        self.moisturemap.set_tile_distance(tile_distance);
+        self.temperatureperturbancemap
+            .set_tile_distance(tile_distance);
+        self.maptexturemap.set_tile_distance(tile_distance);
+    }
+
+    /// The temperature at `latitude`, the second coordinate of a terrain point, given the
+    /// perturbance noise there.
+    fn temperature(&self, latitude: f64, perturbance: f64) -> f64 {
+        let base = ((latitude + 0.25) * 2.0 * PI).sin();
+        let base_weight = self.temperature_base_weight;
+        let perturbance_weight = self.temperature_perturbance_weight;
+        ((base * base_weight + perturbance * perturbance_weight)
+            / (base_weight + perturbance_weight))
+            / 2.0
+            + 0.5
+            + self.temperature_offset
+    }
 }
 
+/// Maps noise from -1 to 1 onto 0 to 1.
+fn unit_range(noise: f64) -> f64 {
+    noise / 2.0 + 0.5
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum MapBiome {
     Empty,
     Water,
@@ -79,128 +314,89 @@ pub enum MapBiome {
     Mountain,
 }
 
+impl MapBiome {
+    pub const ALL: [MapBiome; 14] = [
+        MapBiome::Empty,
+        MapBiome::Water,
+        MapBiome::Ice,
+        MapBiome::Tundra,
+        MapBiome::BorealForest,
+        MapBiome::Shrubland,
+        MapBiome::TemperateGrassland,
+        MapBiome::TemperateRainforest,
+        MapBiome::TemperateSeasonalForest,
+        MapBiome::TropicalRainforest,
+        MapBiome::TropicalSeasonalForest,
+        MapBiome::Savannah,
+        MapBiome::Desert,
+        MapBiome::Mountain,
+    ];
+}
+
 impl Terrain for TiledWorldTerrain {
     fn get_height(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
-        self.heightmap.get_noise(&seed.derive(&"heightmap"), point) / 2.0 + 0.5
+        unit_range(self.heightmap.get_noise(&seed.derive(&"heightmap"), point))
     }
 
     fn get_precipitation(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
-        self.moisturemap
-            .get_noise(&seed.derive(&"moisturemap"), point)
-            / 2.0
-            + 0.5
+        unit_range(
+            self.moisturemap
+                .get_noise(&seed.derive(&"moisturemap"), point),
+        )
     }
 
     fn get_temperature(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
-        let base = ((point.get(1).unwrap() + 0.25) * 2.0 * PI).sin();
-        let base_weight = 1.5;
         let perturbance = self
             .temperatureperturbancemap
             .get_noise(&seed.derive(&"temperatureperturbancemap"), point);
-        let perturbance_weight = 1.0;
-        ((base * base_weight + perturbance * perturbance_weight)
-            / (base_weight + perturbance_weight))
-            / 2.0
-            + 0.5
+        self.temperature(*point.get(1).unwrap(), perturbance)
     }
 
     fn get_map_texture(&self, seed: &ProcSeed, point: &Vec<f64>) -> f64 {
-        self.maptexturemap
-            .get_noise(&seed.derive(&"maptexturemap"), point)
-            / 2.0
-            + 0.5
+        unit_range(
+            self.maptexturemap
+                .get_noise(&seed.derive(&"maptexturemap"), point),
+        )
     }
 
     fn get_biome(&self, seed: &ProcSeed, point: &Vec<f64>) -> MapBiome {
-        // fudged implementation of https://upload.wikimedia.org/wikipedia/commons/6/68/Climate_influence_on_terrestrial_biome.svg
-        let water_level = 0.55;
-        let mountain_level = 0.7;
-
-        let freezing = 0.225;
-        let cold = 0.3;
-        let temperate = 0.6;
-
-        let arid = 0.45;
-        let moist = 0.5;
-        let wet = 0.6;
-
-        let height = self.get_height(seed, point);
-        let precipitation = self.get_precipitation(seed, point);
-        let temperature = self.get_temperature(seed, point);
-
-        if height < water_level {
-            if temperature < freezing {
-                MapBiome::Ice
-            } else {
-                MapBiome::Water
-            }
-        } else if height < mountain_level {
-            if temperature < freezing {
-                MapBiome::Tundra
-            } else if temperature < cold {
-                if precipitation < arid {
-                    MapBiome::TemperateGrassland
-                } else if precipitation < moist {
-                    MapBiome::Shrubland
-                } else {
-                    MapBiome::BorealForest
-                }
-            } else if temperature < temperate {
-                if precipitation < arid {
-                    MapBiome::TemperateGrassland
-                } else if precipitation < moist {
-                    MapBiome::Shrubland
-                } else if precipitation < wet {
-                    MapBiome::TemperateSeasonalForest
-                } else {
-                    MapBiome::TemperateRainforest
-                }
-            } else {
-                if precipitation < arid {
-                    MapBiome::Desert
-                } else if precipitation < moist {
-                    MapBiome::Savannah
-                } else if precipitation < wet {
-                    MapBiome::TropicalSeasonalForest
-                } else {
-                    MapBiome::TropicalRainforest
+        self.biomes.classify(
+            self.get_height(seed, point),
+            self.get_temperature(seed, point),
+            self.get_precipitation(seed, point),
+        )
+    }
+
+    fn get_sample_batch(&self, seed: &ProcSeed, axes: &[Vec<f64>]) -> Vec<TerrainSample> {
+        let heights = self
+            .heightmap
+            .get_noise_batch(&seed.derive(&"heightmap"), axes);
+        let moistures = self
+            .moisturemap
+            .get_noise_batch(&seed.derive(&"moisturemap"), axes);
+        let perturbances = self
+            .temperatureperturbancemap
+            .get_noise_batch(&seed.derive(&"temperatureperturbancemap"), axes);
+        (0..heights.len())
+            .map(|i| {
+                let height = unit_range(heights[i]);
+                let precipitation = unit_range(moistures[i]);
+                let temperature = self.temperature(axes[1][i], perturbances[i]);
+                TerrainSample {
+                    height: height,
+                    temperature: temperature,
+                    precipitation: precipitation,
+                    biome: self.biomes.classify(height, temperature, precipitation),
                 }
-            }
-        } else {
-            MapBiome::Mountain
-        }
+            })
+            .collect()
     }
+
     fn render_qs(&self, seed: &ProcSeed, point: &Vec<f64>) -> (Color, Color) {
-        let (c, fg, _) = match self.get_biome(seed, point) {
-            MapBiome::Empty => ('.', vec![1.0, 1.0, 1.0], vec![0.0, 0.0, 0.0]),
-            MapBiome::Water => (
-                '~',
-                color_lerp(
-                    &vec![0.0, 0.0, 0.3],
-                    &vec![0.0, 0.4, 0.9],
-                    (self.get_height(seed, point) / 0.55).powi(3),
-                ),
-                vec![0.0, 0.0, 0.4],
-            ),
-            MapBiome::Ice => ('.', vec![0.7, 0.7, 1.0], vec![1.0, 1.0, 1.0]),
-            MapBiome::Tundra => (':', vec![0.5, 0.8, 0.4], vec![0.8, 0.9, 0.8]),
-            MapBiome::BorealForest => ('T', vec![0.4, 0.7, 0.4], vec![0.6, 0.9, 0.6]),
-            MapBiome::Shrubland => (';', vec![0.5, 0.6, 0.2], vec![0.6, 0.8, 0.4]),
-            MapBiome::TemperateGrassland => ('.', vec![0.3, 0.7, 0.2], vec![0.6, 0.8, 0.5]),
-            MapBiome::TemperateRainforest => ('T', vec![0.2, 0.6, 0.4], vec![0.5, 0.8, 0.5]),
-            MapBiome::TemperateSeasonalForest => ('t', vec![0.6, 0.8, 0.3], vec![0.6, 0.8, 0.4]),
-            MapBiome::TropicalRainforest => ('T', vec![0.2, 0.8, 0.2], vec![0.1, 0.5, 0.1]),
-            MapBiome::TropicalSeasonalForest => ('t', vec![0.5, 0.7, 0.1], vec![0.2, 0.6, 0.1]),
-            MapBiome::Savannah => ('.', vec![0.5, 0.7, 0.1], vec![0.6, 0.8, 0.4]),
-            MapBiome::Desert => ('~', vec![0.9, 0.8, 0.2], vec![0.8, 0.6, 0.6]),
-            MapBiome::Mountain => ('^', vec![0.8, 0.8, 0.8], vec![0.4, 0.4, 0.4]),
-        };
-        (
-            color_to_cursive_color(&fg),
-            color_to_cursive_color(&color_brightness(
-                &fg,
-                0.3 + 0.7 * self.get_map_texture(seed, point),
-            )),
+        self.render_biome(
+            self.get_biome(seed, point),
+            self.get_height(seed, point),
+            self.get_map_texture(seed, point),
         )
     }
 }
@@ -223,3 +419,98 @@ fn color_to_cursive_color(color: &Vec<f64>) -> Color {
         a: 1.0,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::procgen::procseed::HashVersion;
+
+    /// Pins generated output for known seeds. If one of these changes, every seed players have
+    /// shared changes with it, so bump `HashVersion` instead of updating the expected values.
+    #[test]
+    fn golden_terrain() {
+        let terrain = TiledWorldTerrain::new();
+        let cases = vec![
+            (0, vec![0.0, 0.0], 0.5090312871631107, MapBiome::Water),
+            (0, vec![0.1, 0.9], 0.42081956342041593, MapBiome::Water),
+            (0, vec![0.25, 0.5], 0.4709638844730708, MapBiome::Ice),
+            (0, vec![0.73, 0.41], 0.5289871159123195, MapBiome::Water),
+            (1, vec![0.0, 0.0], 0.48307207036762007, MapBiome::Water),
+            (1, vec![0.1, 0.9], 0.6800969116372761, MapBiome::Savannah),
+            (1, vec![0.25, 0.5], 0.5515949505960849, MapBiome::Tundra),
+            (1, vec![0.9, 0.1], 0.34283863265960196, MapBiome::Water),
+            (2, vec![0.0, 0.0], 0.4948903285580577, MapBiome::Water),
+            (2, vec![0.1, 0.9], 0.41535256392039005, MapBiome::Water),
+            (2, vec![0.25, 0.5], 0.4821504835009626, MapBiome::Ice),
+            (2, vec![0.73, 0.41], 0.5418798553581081, MapBiome::Water),
+            (3, vec![0.0, 0.0], 0.470972104584201, MapBiome::Water),
+            (3, vec![0.1, 0.9], 0.7065366309873687, MapBiome::Mountain),
+            (3, vec![0.25, 0.5], 0.5391666072916075, MapBiome::Ice),
+            (3, vec![0.9, 0.1], 0.33283565083219785, MapBiome::Water),
+        ];
+        let seeds = vec![
+            ProcSeed::with_version(&0u32, 0.0, HashVersion::V1),
+            ProcSeed::with_version(&"nations", 0.5, HashVersion::V1),
+            ProcSeed::with_version(&0u32, 0.0, HashVersion::V2),
+            ProcSeed::with_version(&"nations", 0.5, HashVersion::V2),
+        ];
+        for (seed, point, height, biome) in cases {
+            let seed = &seeds[seed];
+            let actual = terrain.get_height(seed, &point);
+            assert!(
+                (actual - height).abs() < 1e-9,
+                "height at {:?}: expected {}, got {}",
+                point,
+                height,
+                actual
+            );
+            assert_eq!(
+                terrain.get_biome(seed, &point),
+                biome,
+                "biome at {:?}",
+                point
+            );
+        }
+    }
+
+    #[test]
+    fn parallel_regions_match_serial_samples() {
+        let terrain = TiledWorldTerrain::new();
+        let seed = ProcSeed::new(&"region", 0.25);
+        let point = |x: usize, y: usize| vec![x as f64 / 37.0, y as f64 / 23.0];
+        let region = terrain.sample_region(&seed, 37, 23, point);
+        assert_eq!(region.len(), 37 * 23);
+        for (i, sample) in region.samples().enumerate() {
+            let serial = terrain.get_sample(&seed, &point(i % 37, i / 37));
+            assert_eq!(sample.height.to_bits(), serial.height.to_bits());
+            assert_eq!(sample.temperature.to_bits(), serial.temperature.to_bits());
+            assert_eq!(
+                sample.precipitation.to_bits(),
+                serial.precipitation.to_bits()
+            );
+            assert_eq!(sample.biome, serial.biome);
+        }
+    }
+
+    #[test]
+    fn batches_match_single_points() {
+        let terrain = TiledWorldTerrain::new();
+        let seed = ProcSeed::new(&"batch", 0.75);
+        // an odd length leaves some lanes of the last simplex batch unused
+        let points: Vec<Vec<f64>> = (0..61)
+            .map(|i| vec![(i as f64 * 0.173).fract(), (i as f64 * 0.311).fract() - 0.5])
+            .collect();
+        let samples = terrain.get_sample_batch(&seed, &to_axes(&points));
+        assert_eq!(samples.len(), points.len());
+        for (point, sample) in points.iter().zip(samples) {
+            let single = terrain.get_sample(&seed, point);
+            assert_eq!(sample.height.to_bits(), single.height.to_bits());
+            assert_eq!(sample.temperature.to_bits(), single.temperature.to_bits());
+            assert_eq!(
+                sample.precipitation.to_bits(),
+                single.precipitation.to_bits()
+            );
+            assert_eq!(sample.biome, single.biome);
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use crate::game::world::TiledGameWorld;
+use crate::geometry::{Hex, HexRange, Layout, Orientation};
+use crate::procgen::procseed::ProcSeed;
+use crate::procgen::terrain::Terrain;
+
+use quicksilver::geom::Vector;
+use quicksilver::graphics::Color;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// How image pixels are mapped onto the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampling {
+    /// Every pixel samples the terrain at its own position.
+    Square,
+    /// Pixels are grouped into hexes of the given radius in pixels, laid out with
+    /// `RenderOptions::orientation` from the top left corner, and each hex is filled with the
+    /// sample at its centre.
+    Hex(f32),
+}
+
+/// Which terrain property is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    /// Flat biome colours, the first colour of `Terrain::render_qs`.
+    Biome,
+    /// Biome colours shaded by the map texture, as shown in the game window.
+    Shaded,
+    Height,
+    Temperature,
+    Precipitation,
+    Texture,
+}
+
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    pub sampling: Sampling,
+    pub channel: Channel,
+    pub orientation: Orientation,
+}
+
+impl RenderOptions {
+    pub fn new(width: u32, height: u32) -> RenderOptions {
+        RenderOptions {
+            width: width,
+            height: height,
+            sampling: Sampling::Square,
+            channel: Channel::Shaded,
+            orientation: Orientation::POINTY,
+        }
+    }
+}
+
+/// An RGB raster of a rendered map, independent of any window or GPU context.
+pub struct MapImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl MapImage {
+    pub fn new(width: u32, height: u32) -> MapImage {
+        MapImage {
+            width: width,
+            height: height,
+            pixels: vec![Color::BLACK; (width * height) as usize],
+        }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Color) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    pub fn write_png<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(w, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        let data: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|c| vec![to_byte(c.r), to_byte(c.g), to_byte(c.b)])
+            .collect();
+        writer.write_image_data(&data)?;
+        Ok(())
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Renders the whole world, stretched to the requested resolution.
+pub fn render_world(world: &TiledGameWorld, seed: &ProcSeed, options: &RenderOptions) -> MapImage {
+    let mut image = MapImage::new(options.width, options.height);
+    let scale_x = world.get_width() as f64 / options.width as f64;
+    let scale_y = world.get_height() as f64 / options.height as f64;
+    let sample = |x: f32, y: f32| {
+        sample_channel(
+            world,
+            seed,
+            x as f64 * scale_x,
+            y as f64 * scale_y,
+            options.channel,
+        )
+    };
+
+    // sampling is done in parallel, and only the cheap filling in is serial
+    match options.sampling {
+        Sampling::Square => {
+            let width = options.width;
+            image.pixels = (0..width * options.height)
+                .into_par_iter()
+                .map(|i| sample((i % width) as f32 + 0.5, (i / width) as f32 + 0.5))
+                .collect();
+        }
+        Sampling::Hex(radius) => {
+            let layout = Layout::with_radius(options.orientation, radius, Vector::ZERO);
+            let hexes: Vec<Hex> = covering_range(&layout, options.width, options.height)
+                .iter()
+                .collect();
+            let colors: Vec<Color> = hexes
+                .par_iter()
+                .map(|hex| {
+                    let centre = layout.to_screen(hex);
+                    sample(centre.x, centre.y)
+                })
+                .collect();
+            for (hex, color) in hexes.iter().zip(colors) {
+                fill_hex(&mut image, &layout, hex, color);
+            }
+        }
+    }
+    image
+}
+
+/// Samples the channel at a position in world units.
+fn sample_channel(
+    world: &TiledGameWorld,
+    seed: &ProcSeed,
+    x: f64,
+    y: f64,
+    channel: Channel,
+) -> Color {
+    let terrain = world.get_terrain();
+    let point = &world.get_terrain_point(x, y);
+    if world.get_heightmap(seed).is_some() {
+        // eroded heights are stored per tile, and they change the biome too
+        let sample = world.get_sample_at(seed, x, y);
+        let render = |texture| terrain.render_biome(sample.biome, sample.height, texture);
+        match channel {
+            Channel::Biome => return render(0.0).0,
+            Channel::Shaded => return render(terrain.get_map_texture(seed, point)).1,
+            Channel::Height => return grey(sample.height),
+            _ => {}
+        }
+    }
+    match channel {
+        Channel::Biome => terrain.render_qs(seed, point).0,
+        Channel::Shaded => terrain.render_qs(seed, point).1,
+        Channel::Height => grey(terrain.get_height(seed, point)),
+        Channel::Temperature => grey(terrain.get_temperature(seed, point)),
+        Channel::Precipitation => grey(terrain.get_precipitation(seed, point)),
+        Channel::Texture => grey(terrain.get_map_texture(seed, point)),
+    }
+}
+
+/// The smallest range of hexes that covers an image. Cube coordinates are linear in screen
+/// position, so their extremes over the image are found at its corners.
+fn covering_range(layout: &Layout, width: u32, height: u32) -> HexRange {
+    let corners: Vec<Hex> = [(0, 0), (width, 0), (0, height), (width, height)]
+        .iter()
+        .map(|(x, y)| layout.pick(Vector::new(*x as f32, *y as f32)))
+        .collect();
+    let extreme = |f: fn(&Hex) -> isize, best: fn(isize, isize) -> isize, margin: isize| {
+        corners.iter().map(f).fold(f(&corners[0]), best) + margin
+    };
+    HexRange {
+        min: Hex::new(
+            extreme(|h| h.x, std::cmp::min, -1),
+            extreme(|h| h.y, std::cmp::min, -1),
+            extreme(|h| h.z, std::cmp::min, -1),
+        ),
+        max: Hex::new(
+            extreme(|h| h.x, std::cmp::max, 1),
+            extreme(|h| h.y, std::cmp::max, 1),
+            extreme(|h| h.z, std::cmp::max, 1),
+        ),
+    }
+}
+
+/// Fills the pixels whose centres fall inside `hex` as drawn by `HexShape`.
+fn fill_hex(image: &mut MapImage, layout: &Layout, hex: &Hex, color: Color) {
+    let centre = layout.to_screen(hex);
+    let half_w = layout.size.x / 2.0;
+    let half_h = layout.size.y / 2.0;
+    let min_x = (centre.x - half_w).floor().max(0.0) as u32;
+    let min_y = (centre.y - half_h).floor().max(0.0) as u32;
+    let max_x = ((centre.x + half_w).ceil().max(0.0) as u32).min(image.width);
+    let max_y = ((centre.y + half_h).ceil().max(0.0) as u32).min(image.height);
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            if layout.pick(Vector::new(x as f32 + 0.5, y as f32 + 0.5)) == *hex {
+                image.set(x, y, color);
+            }
+        }
+    }
+}
+
+fn grey(value: f64) -> Color {
+    let v = value.clamp(0.0, 1.0) as f32;
+    Color {
+        r: v,
+        g: v,
+        b: v,
+        a: 1.0,
+    }
+}
+
+fn to_byte(channel: f32) -> u8 {
+    (channel.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_small_png() {
+        let world = TiledGameWorld::new(40, 30);
+        let seed = ProcSeed::new(&0u32, 0.0);
+        let options = [
+            (Sampling::Square, Orientation::POINTY),
+            (Sampling::Hex(3.0), Orientation::POINTY),
+            (Sampling::Hex(3.0), Orientation::FLAT),
+        ];
+        for (sampling, orientation) in options.iter() {
+            let mut options = RenderOptions::new(8, 6);
+            options.sampling = *sampling;
+            options.orientation = *orientation;
+            options.channel = Channel::Height;
+            let image = render_world(&world, &seed, &options);
+            assert!(image.pixels.iter().all(|c| c.r > 0.0 && c.r < 1.0));
+
+            let mut png = Vec::new();
+            image.write_png(&mut png).unwrap();
+            assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        }
+    }
+}